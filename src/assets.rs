@@ -1,39 +1,175 @@
+use clap::ValueEnum;
+use serde::Deserialize;
+
 pub const SHELL_HTML: &str = include_str!("assets/shell.html");
 pub const GITHUB_CSS: &str = include_str!("assets/github.css");
 pub const GITLAB_CSS: &str = include_str!("assets/gitlab.css");
 pub const BASE_CSS: &str = include_str!("assets/base.css");
 pub const APP_JS: &str = include_str!("assets/app.js");
 
+/// Third-party files served from `/vendor/` so rendering works offline.
+/// Loaded on demand rather than inlined into every page.
+/// Each entry is (path under `/vendor/`, content type, body). Their licences
+/// sit next to them: `vendor/mermaid.LICENSE` and `vendor/math/LICENSE`.
+pub const VENDOR_FILES: &[(&str, &str, &[u8])] = &[
+    ("mermaid.min.js", "text/javascript; charset=utf-8", include_bytes!("assets/vendor/mermaid.min.js")),
+    // MathML stylesheet and Latin Modern fonts from pulldown-latex
+    ("math/styles.css", "text/css; charset=utf-8", include_bytes!("assets/vendor/math/styles.css")),
+    ("math/font/latinmodern-math.woff2", "font/woff2", include_bytes!("assets/vendor/math/font/latinmodern-math.woff2")),
+    ("math/font/lmroman12-regular.woff2", "font/woff2", include_bytes!("assets/vendor/math/font/lmroman12-regular.woff2")),
+    ("math/font/lmroman12-bold.woff2", "font/woff2", include_bytes!("assets/vendor/math/font/lmroman12-bold.woff2")),
+    ("math/font/lmroman12-italic.woff2", "font/woff2", include_bytes!("assets/vendor/math/font/lmroman12-italic.woff2")),
+];
+
+/// Markdown stylesheet applied until the reader picks one with the style toggle.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum MarkdownStyle {
+    #[default]
+    Github,
+    Gitlab,
+}
+
+impl MarkdownStyle {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Github => "github",
+            Self::Gitlab => "gitlab",
+        }
+    }
+}
+
 /// A pre-built page shell with all static assets baked in.
-/// Only `{title}`, `{content}`, `{syntax_css_light}`, and `{syntax_css_dark}` remain as placeholders.
+/// Only `{title}`, `{content}`, `{syntax_css_light}`, `{syntax_css_dark}`,
+/// `{base}` and the static-export slots (`{mode}`, `{asset_base}`,
+/// `{sidebar}`, `{toc}`) remain as placeholders.
 pub struct PageShell {
     template: String,
+    /// `{mode}` of live pages: `live`, or `file` in single-file mode.
+    live_mode: &'static str,
+}
+
+/// Prebuilt parts of a statically exported page, which has no server to
+/// fetch the sidebar or table of contents from.
+pub struct ExportSlots<'a> {
+    /// URL prefix of a live page's root; always empty for an export.
+    pub base: &'a str,
+    /// Relative URL of the exported vendor directory, ending in `/`.
+    pub asset_base: &'a str,
+    pub sidebar: &'a str,
+    pub toc: &'a str,
 }
 
 impl PageShell {
-    pub fn new() -> Self {
+    pub fn new(style: MarkdownStyle) -> Self {
         let template = SHELL_HTML
+            .replace("{style}", style.name())
             .replace("{github_css}", GITHUB_CSS)
             .replace("{gitlab_css}", GITLAB_CSS)
             .replace("{base_css}", BASE_CSS)
             .replace("{app_js}", APP_JS);
-        Self { template }
+        Self { template, live_mode: "live" }
     }
 
-    pub fn render(&self, title: &str, content: &str, syntax_css_light: &str, syntax_css_dark: &str) -> String {
-        self.template
-            .replace("{title}", title)
-            .replace("{content}", content)
-            .replace("{syntax_css_light}", syntax_css_light)
-            .replace("{syntax_css_dark}", syntax_css_dark)
+    /// Render live pages without the file sidebar, for previewing one file.
+    pub fn single_file(mut self) -> Self {
+        self.live_mode = "file";
+        self
     }
 
-    pub fn render_empty(&self, syntax_css_light: &str, syntax_css_dark: &str) -> String {
-        self.render(
-            "No files",
-            "<p>No markdown files found in this directory.</p>",
-            syntax_css_light,
-            syntax_css_dark,
-        )
+    /// Render a live page. `base` is the URL prefix of the page's root (see
+    /// [`RenderOptions::base`](crate::render::RenderOptions::base)).
+    pub fn render(
+        &self,
+        base: &str,
+        title: &str,
+        content: &str,
+        syntax_css_light: &str,
+        syntax_css_dark: &str,
+    ) -> String {
+        let live = ExportSlots { base, asset_base: "/vendor/", sidebar: "", toc: "" };
+        self.fill(self.live_mode, &live, title, content, syntax_css_light, syntax_css_dark)
+    }
+
+    /// Render a self-contained page for static export: live reload, search and
+    /// API fetches are disabled in `app.js`.
+    pub fn render_export(
+        &self,
+        slots: &ExportSlots,
+        title: &str,
+        content: &str,
+        syntax_css_light: &str,
+        syntax_css_dark: &str,
+    ) -> String {
+        self.fill("static", slots, title, content, syntax_css_light, syntax_css_dark)
+    }
+
+    fn fill(
+        &self,
+        mode: &str,
+        slots: &ExportSlots,
+        title: &str,
+        content: &str,
+        syntax_css_light: &str,
+        syntax_css_dark: &str,
+    ) -> String {
+        let value = |name: &str| match name {
+            "title" => Some(title),
+            "mode" => Some(mode),
+            "base" => Some(slots.base),
+            "asset_base" => Some(slots.asset_base),
+            "sidebar" => Some(slots.sidebar),
+            "toc" => Some(slots.toc),
+            "syntax_css_light" => Some(syntax_css_light),
+            "syntax_css_dark" => Some(syntax_css_dark),
+            "content" => Some(content),
+            _ => None,
+        };
+        // One pass over the template, so placeholder-like text in titles,
+        // file names or documents is never expanded itself.
+        let mut page = String::with_capacity(self.template.len() + content.len());
+        let mut rest = self.template.as_str();
+        while let Some(start) = rest.find('{') {
+            page.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let slot = rest.find('}').and_then(|end| Some((end, value(&rest[1..end])?)));
+            match slot {
+                Some((end, value)) => {
+                    page.push_str(value);
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    page.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        page.push_str(rest);
+        page
+    }
+
+    pub fn render_empty(&self, base: &str, syntax_css_light: &str, syntax_css_dark: &str) -> String {
+        let message = match self.live_mode {
+            "file" => "<p>The file does not exist yet; this page reloads when it is created.</p>",
+            _ => "<p>No markdown files found in this directory.</p>",
+        };
+        self.render(base, "No files", message, syntax_css_light, syntax_css_dark)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders_in_values_are_left_alone() {
+        let shell = PageShell::new(MarkdownStyle::default());
+        let slots = ExportSlots { base: "", asset_base: "_assets/", sidebar: "{toc}", toc: "<li>toc</li>" };
+        let page = shell.render_export(&slots, "{content} {sidebar}", "<p>{title}</p>", "", "");
+        assert!(page.contains("<title>{content} {sidebar}"), "{page}");
+        assert!(page.contains("<p>{title}</p>"));
+        assert!(page.contains("<li>toc</li>"));
+        assert_eq!(page.matches("<li>toc</li>").count(), 1);
+        assert!(!page.contains("{asset_base}"));
     }
 }
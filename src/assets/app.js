@@ -3,62 +3,389 @@
         return path.split('/').map(encodeURIComponent).join('/');
     }
 
-    const currentPath = () => decodeURIComponent(location.pathname.replace(/^\/view\//, ''));
+    // Static exports have the sidebar and TOC prebuilt and no server to talk to.
+    const isStatic = document.body.dataset.mode === 'static';
+    // Single-file mode previews one document, so there is no file sidebar.
+    const isSingleFile = document.body.dataset.mode === 'file';
+    // With several roots every page lives under `/<root name>`, its base.
+    const mounted = Boolean(document.body.dataset.base);
+
+    const viewUrl = (base, path) => base + '/view/' + encodePath(path);
+
+    // Split a `/view/...` URL path into the root's base and the file path, or null.
+    function parseViewUrl(pathname) {
+        const end = pathname.indexOf('/', 1);
+        const base = mounted && end > 0 ? pathname.slice(0, end) : '';
+        const prefix = base + '/view/';
+        if (!pathname.startsWith(prefix)) return null;
+        return { base, path: decodeURIComponent(pathname.slice(prefix.length)) };
+    }
+
+    const currentView = () => parseViewUrl(location.pathname) || { base: document.body.dataset.base, path: '' };
+    const currentPath = () => currentView().path;
+    const currentBase = () => currentView().base;
+    const assetBase = document.body.dataset.assetBase;
+
+    // The element a link fragment names. The sanitizer prefixes ids in the
+    // document with `user-content-` so they can't clobber the page's own,
+    // while links keep the bare name; unsanitized documents keep theirs.
+    function findAnchor(name) {
+        return document.getElementById('user-content-' + name)
+            || document.querySelector('.markdown-body').querySelector('[id="' + CSS.escape(name) + '"]');
+    }
+
+    // Scroll the heading named by `hash` (e.g. "#setup") into view, if present.
+    function scrollToHash(hash) {
+        const target = hash && findAnchor(decodeURIComponent(hash.slice(1)));
+        if (target) target.scrollIntoView();
+        return Boolean(target);
+    }
 
     // SSE
-    const es = new EventSource('/events');
+    const es = isStatic ? {} : new EventSource('/events');
     es.onmessage = (e) => {
         const event = JSON.parse(e.data);
-        if (event.type === 'FileChanged' && event.path === currentPath()) {
-            fetch('/raw/' + encodePath(currentPath()))
+        const base = event.root ? '/' + event.root : '';
+        const isCurrent = base === currentBase() && event.path === currentPath();
+        if ((event.type === 'FileChanged' || event.type === 'FileAdded') && isCurrent) {
+            fetch(base + '/raw/' + encodePath(currentPath()))
                 .then(r => r.text())
-                .then(html => { document.querySelector('.markdown-body').innerHTML = html; });
+                .then(html => {
+                    document.querySelector('.markdown-body').innerHTML = html;
+                    reloadImages(base);
+                    renderDiagrams();
+                    scrollToHash(location.hash);
+                });
+            loadToc(base, currentPath());
         }
-        if (event.type === 'FileAdded' || event.type === 'FileRemoved') {
+        if (isSingleFile && event.type === 'FileAdded' && !currentPath()) {
+            location.reload(); // the file appeared; `/` now redirects to it
+        }
+        if ((event.type === 'FileAdded' || event.type === 'FileRemoved') && !isSingleFile) {
             loadSidebar();
         }
     };
 
+    // The browser reuses images it already has for the same URL, so bust
+    // served images after a reload in case one of them was edited.
+    function reloadImages(base) {
+        document.querySelectorAll('.markdown-body img').forEach(img => {
+            const url = new URL(img.src, location.href);
+            if (url.origin !== location.origin || !url.pathname.startsWith(base + '/file/')) return;
+            url.searchParams.set('v', Date.now());
+            img.src = url.href;
+        });
+    }
+
     // Sidebar
-    async function loadSidebar() {
-        const res = await fetch('/api/files');
-        const files = await res.json();
+    const openDirs = new Set(JSON.parse(localStorage.getItem('md-preview-open-dirs') || '[]'));
+    function saveOpenDirs() {
+        localStorage.setItem('md-preview-open-dirs', JSON.stringify([...openDirs]));
+    }
+
+    function renderTree(nodes, parent, base) {
+        nodes.forEach(node => {
+            const li = document.createElement('li');
+            if (node.type === 'root') {
+                li.className = 'root';
+                const name = document.createElement('div');
+                name.className = 'root-name';
+                name.textContent = node.name;
+                const children = document.createElement('ul');
+                renderTree(node.children, children, '/' + node.name);
+                li.append(name, children);
+            } else if (node.type === 'dir') {
+                li.className = 'dir';
+                li.dataset.path = base ? base.slice(1) + '/' + node.path : node.path;
+                const toggle = document.createElement('button');
+                toggle.className = 'dir-toggle';
+                toggle.textContent = node.name;
+                const count = document.createElement('span');
+                count.className = 'count';
+                count.textContent = node.count;
+                toggle.appendChild(count);
+                const children = document.createElement('ul');
+                renderTree(node.children, children, base);
+                li.append(toggle, children);
+                bindDir(li);
+            } else {
+                const a = document.createElement('a');
+                a.href = viewUrl(base, node.path);
+                a.textContent = node.name;
+                a.title = node.path;
+                a.onclick = (e) => {
+                    e.preventDefault();
+                    navigateTo(base, node.path);
+                };
+                if (base === currentBase() && node.path === currentPath()) a.classList.add('active');
+                li.appendChild(a);
+            }
+            parent.appendChild(li);
+        });
+    }
+
+    // Restore a folder's open state and make its toggle button work.
+    function bindDir(li) {
+        li.classList.toggle('open', openDirs.has(li.dataset.path));
+        li.querySelector('.dir-toggle').onclick = () => {
+            const open = li.classList.toggle('open');
+            if (open) openDirs.add(li.dataset.path); else openDirs.delete(li.dataset.path);
+            saveOpenDirs();
+        };
+    }
+
+    // Expand the folders containing the active file so it is visible. Only
+    // called when a page is opened; the folders are remembered like ones the
+    // user opened, so a later sidebar refresh keeps whatever they collapse.
+    function revealActive() {
+        let el = document.querySelector('#file-tree a.active');
+        while (el && (el = el.parentElement.closest('li.dir'))) {
+            el.classList.add('open');
+            openDirs.add(el.dataset.path);
+        }
+        saveOpenDirs();
+    }
+
+    async function loadSidebar(reveal = false) {
+        const res = await fetch('/api/files?tree=true');
+        const nodes = await res.json();
         const tree = document.getElementById('file-tree');
         tree.innerHTML = '';
-        files.forEach(f => {
+        renderTree(nodes, tree, '');
+        if (reveal) revealActive();
+    }
+
+    // Table of contents
+    const tocList = document.getElementById('toc-list');
+    let tocRequest = 0; // only the latest request may fill the list
+    async function loadToc(base, path) {
+        const request = ++tocRequest;
+        const res = await fetch(base + '/api/toc/' + encodePath(path));
+        const entries = res.ok ? await res.json() : [];
+        if (request !== tocRequest) return;
+        tocList.innerHTML = '';
+        const minLevel = Math.min(...entries.map(e => e.level));
+        entries.forEach(entry => {
             const li = document.createElement('li');
             const a = document.createElement('a');
-            a.href = '/view/' + encodePath(f);
-            a.textContent = f;
-            a.onclick = (e) => {
-                e.preventDefault();
-                navigateTo(f);
-            };
-            if (f === currentPath()) a.classList.add('active');
+            a.href = '#' + encodeURIComponent(entry.slug);
+            a.textContent = entry.text;
+            a.dataset.slug = entry.slug;
+            a.style.paddingLeft = (8 + (entry.level - minLevel) * 12) + 'px';
             li.appendChild(a);
-            tree.appendChild(li);
+            tocList.appendChild(li);
+        });
+        updateScrollSpy();
+    }
+
+    // Scroll spy: highlight the last heading scrolled past the top of <main>
+    const main = document.querySelector('main');
+    function updateScrollSpy() {
+        const links = tocList.querySelectorAll('a');
+        const top = main.getBoundingClientRect().top + 16;
+        let active = null;
+        links.forEach(a => {
+            const heading = findAnchor(a.dataset.slug);
+            if (heading && heading.getBoundingClientRect().top <= top) active = a;
         });
+        if (!active && links.length) active = links[0];
+        links.forEach(a => a.classList.toggle('active', a === active));
     }
+    main.addEventListener('scroll', updateScrollSpy, { passive: true });
 
-    async function renderPath(path) {
-        const res = await fetch('/raw/' + encodePath(path));
+    function setTocCollapsed(collapsed) {
+        document.body.classList.toggle('toc-collapsed', collapsed);
+        localStorage.setItem('md-preview-toc-collapsed', collapsed ? '1' : '0');
+    }
+    document.getElementById('toc-toggle').onclick = () => {
+        setTocCollapsed(!document.body.classList.contains('toc-collapsed'));
+    };
+    setTocCollapsed(localStorage.getItem('md-preview-toc-collapsed') === '1');
+
+    let renderedUrl = viewUrl(currentBase(), currentPath());
+
+    async function renderPath(base, path) {
+        const res = await fetch(base + '/raw/' + encodePath(path));
         const html = await res.text();
         document.querySelector('.markdown-body').innerHTML = html;
+        renderDiagrams();
+        renderedUrl = viewUrl(base, path);
+        loadToc(base, path);
         document.querySelectorAll('#file-tree a').forEach(a => {
-            a.classList.toggle('active', decodeURIComponent(a.pathname) === '/view/' + path);
+            a.classList.toggle('active', decodeURIComponent(a.pathname) === base + '/view/' + path);
         });
+        revealActive();
     }
 
-    async function navigateTo(path) {
-        await renderPath(path);
-        history.pushState(null, '', '/view/' + encodePath(path));
+    async function navigateTo(base, path, hash = '') {
+        await renderPath(base, path);
+        history.pushState(null, '', viewUrl(base, path) + hash);
+        if (!scrollToHash(hash)) document.querySelector('main').scrollTop = 0;
     }
 
-    window.onpopstate = () => {
-        const path = currentPath();
-        if (path) renderPath(path);
+    // Fragment links (headings, footnotes, the TOC) name unprefixed ids, so
+    // the browser can't follow them by itself.
+    document.addEventListener('click', (e) => {
+        const a = e.target.closest('a[href^="#"]');
+        if (!a || e.button !== 0 || e.metaKey || e.ctrlKey || e.shiftKey || e.altKey) return;
+        if (!scrollToHash(a.hash)) return;
+        e.preventDefault();
+        if (a.hash !== location.hash) history.pushState(null, '', a.hash);
+    });
+
+    // In-document links to other markdown files (rewritten to /view/... by the server)
+    document.querySelector('.markdown-body').addEventListener('click', (e) => {
+        const a = e.target.closest('a:not([href^="#"])');
+        const view = a && !isStatic && a.origin === location.origin && parseViewUrl(a.pathname);
+        if (!view) return;
+        if (e.button !== 0 || e.metaKey || e.ctrlKey || e.shiftKey || e.altKey) return;
+        e.preventDefault();
+        navigateTo(view.base, view.path, a.hash);
+    });
+
+    window.onpopstate = async () => {
+        const { base, path } = currentView();
+        // Fragment-only history entries (anchor clicks) don't need a re-render
+        if (!isStatic && path && viewUrl(base, path) !== renderedUrl) await renderPath(base, path);
+        scrollToHash(location.hash);
     };
 
+    // Search
+    if (isStatic) document.querySelector('.search').remove();
+    const searchInput = document.getElementById('search');
+    const searchResults = document.getElementById('search-results');
+    let searchHits = [];
+    let selectedHit = -1;
+    let searchTimer = null;
+
+    function closeSearch() {
+        searchResults.hidden = true;
+        selectedHit = -1;
+    }
+
+    function selectHit(index) {
+        const items = searchResults.querySelectorAll('li[data-index]');
+        if (!items.length) return;
+        selectedHit = (index + items.length) % items.length;
+        items.forEach((li, i) => li.classList.toggle('selected', i === selectedHit));
+        items[selectedHit].scrollIntoView({ block: 'nearest' });
+    }
+
+    function openHit(index) {
+        const hit = searchHits[index];
+        if (!hit) return;
+        closeSearch();
+        searchInput.blur();
+        navigateTo(hit.root ? '/' + hit.root : '', hit.path, hit.slug ? '#' + encodeURIComponent(hit.slug) : '');
+    }
+
+    async function runSearch(query) {
+        if (!query.trim()) {
+            closeSearch();
+            return;
+        }
+        const res = await fetch('/api/search?q=' + encodeURIComponent(query));
+        if (query !== searchInput.value) return; // a newer query is in flight
+        searchHits = await res.json();
+        searchResults.innerHTML = '';
+        if (!searchHits.length) {
+            const li = document.createElement('li');
+            li.className = 'empty';
+            li.textContent = 'No results';
+            searchResults.appendChild(li);
+        }
+        searchHits.forEach((hit, i) => {
+            const li = document.createElement('li');
+            li.dataset.index = i;
+            const title = document.createElement('div');
+            title.className = 'hit-path';
+            title.textContent = hit.root ? hit.root + '/' + hit.path : hit.path;
+            if (hit.heading) {
+                const heading = document.createElement('span');
+                heading.className = 'hit-heading';
+                heading.textContent = ' › ' + hit.heading;
+                title.appendChild(heading);
+            }
+            const snippet = document.createElement('div');
+            snippet.className = 'hit-snippet';
+            snippet.innerHTML = hit.snippet; // escaped server-side, only <mark> added
+            li.append(title, snippet);
+            li.onmousedown = (e) => {
+                e.preventDefault();
+                openHit(i);
+            };
+            searchResults.appendChild(li);
+        });
+        searchResults.hidden = false;
+        selectedHit = -1;
+        if (searchHits.length) selectHit(0);
+    }
+
+    searchInput.addEventListener('input', () => {
+        clearTimeout(searchTimer);
+        searchTimer = setTimeout(() => runSearch(searchInput.value), 120);
+    });
+    searchInput.addEventListener('keydown', (e) => {
+        if (e.key === 'ArrowDown') {
+            e.preventDefault();
+            selectHit(selectedHit + 1);
+        } else if (e.key === 'ArrowUp') {
+            e.preventDefault();
+            selectHit(selectedHit - 1);
+        } else if (e.key === 'Enter') {
+            e.preventDefault();
+            openHit(selectedHit);
+        } else if (e.key === 'Escape') {
+            closeSearch();
+            searchInput.blur();
+        }
+    });
+    searchInput.addEventListener('focus', () => {
+        if (searchInput.value.trim()) runSearch(searchInput.value);
+    });
+    searchInput.addEventListener('blur', closeSearch);
+    document.addEventListener('keydown', (e) => {
+        if (e.key === '/' && document.activeElement !== searchInput && !e.ctrlKey && !e.metaKey) {
+            e.preventDefault();
+            searchInput.focus();
+            searchInput.select();
+        }
+    });
+
+    // Mermaid diagrams: the renderer is large, so it is only fetched when a page has diagrams.
+    let mermaidLoaded = null;
+    function loadMermaid() {
+        if (!mermaidLoaded) {
+            mermaidLoaded = new Promise((resolve, reject) => {
+                const script = document.createElement('script');
+                script.src = assetBase + 'mermaid.min.js';
+                script.onload = resolve;
+                script.onerror = reject;
+                document.head.appendChild(script);
+            });
+        }
+        return mermaidLoaded;
+    }
+
+    async function renderDiagrams() {
+        const blocks = [...document.querySelectorAll('.markdown-body pre.mermaid')];
+        if (!blocks.length) return;
+        await loadMermaid();
+        const dark = document.body.classList.contains('theme-dark');
+        mermaid.initialize({ startOnLoad: false, securityLevel: 'strict', theme: dark ? 'dark' : 'default' });
+        blocks.forEach(block => {
+            // Keep the source so diagrams can be redrawn when the theme changes
+            if (block.dataset.source === undefined) block.dataset.source = block.textContent;
+            block.textContent = block.dataset.source;
+            block.removeAttribute('data-processed');
+        });
+        try {
+            await mermaid.run({ nodes: blocks });
+        } catch (err) {
+            console.error('Mermaid rendering failed', err);
+        }
+    }
+
     // Theme (Light/Dark)
     const themeToggle = document.getElementById('theme-toggle');
     function setTheme(theme) {
@@ -69,6 +396,7 @@
     }
     themeToggle.onclick = () => {
         setTheme(document.body.classList.contains('theme-light') ? 'dark' : 'light');
+        renderDiagrams();
     };
     const savedTheme = localStorage.getItem('md-preview-theme') || 'light';
     setTheme(savedTheme);
@@ -84,8 +412,18 @@
     styleToggle.onclick = () => {
         setStyle(document.body.classList.contains('style-github') ? 'gitlab' : 'github');
     };
-    const savedStyle = localStorage.getItem('md-preview-style') || 'github';
+    const savedStyle = localStorage.getItem('md-preview-style')
+        || (document.body.classList.contains('style-gitlab') ? 'gitlab' : 'github');
     setStyle(savedStyle);
 
-    loadSidebar();
+    if (isStatic) {
+        document.querySelectorAll('#file-tree li.dir').forEach(bindDir);
+        revealActive();
+        updateScrollSpy();
+    } else {
+        if (!isSingleFile) loadSidebar(true);
+        if (currentPath()) loadToc(currentBase(), currentPath());
+    }
+    renderDiagrams();
+    scrollToHash(location.hash);
 })();
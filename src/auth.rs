@@ -0,0 +1,135 @@
+use axum::extract::{Request, State};
+use axum::http::{HeaderValue, StatusCode, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Redirect, Response};
+use percent_encoding::{NON_ALPHANUMERIC, percent_decode_str, utf8_percent_encode};
+use std::sync::Arc;
+use subtle::ConstantTimeEq;
+
+/// Query parameter carrying the token in the URL printed at startup.
+pub const TOKEN_PARAM: &str = "token";
+/// Cookie the token is exchanged for, so later requests and `/events` need no parameter.
+const TOKEN_COOKIE: &str = "marpe_token";
+
+/// A random 128-bit token, hex encoded.
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 16];
+    getrandom::fill(&mut bytes).expect("Failed to read random bytes");
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Middleware that rejects every request without the access token. A valid
+/// `?token=` parameter is answered with a redirect to the same URL without it,
+/// setting a cookie that authorizes later requests.
+pub async fn require_token(State(token): State<Arc<str>>, request: Request, next: Next) -> Response {
+    let headers = request.headers();
+    let from_cookie = headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .any(|(name, value)| name == TOKEN_COOKIE && matches(value, &token));
+    if from_cookie {
+        return next.run(request).await;
+    }
+
+    let query = request.uri().query().unwrap_or("");
+    let from_query = query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .any(|(name, value)| name == TOKEN_PARAM && matches(value, &token));
+    if !from_query {
+        return (StatusCode::UNAUTHORIZED, "Access token required: open the URL printed by marpe at startup")
+            .into_response();
+    }
+
+    let rest: Vec<&str> = query
+        .split('&')
+        .filter(|pair| pair.split_once('=').map_or(*pair, |(name, _)| name) != TOKEN_PARAM)
+        .collect();
+    let location = if rest.is_empty() {
+        request.uri().path().to_string()
+    } else {
+        format!("{}?{}", request.uri().path(), rest.join("&"))
+    };
+    let mut response = Redirect::to(&location).into_response();
+    let cookie = format!(
+        "{TOKEN_COOKIE}={}; Path=/; HttpOnly; SameSite=Strict",
+        utf8_percent_encode(&token, NON_ALPHANUMERIC)
+    );
+    if let Ok(value) = HeaderValue::from_str(&cookie) {
+        response.headers_mut().insert(header::SET_COOKIE, value);
+    }
+    response
+}
+
+/// Compare a percent-encoded cookie or query value with the token in constant time.
+fn matches(given: &str, token: &str) -> bool {
+    let given: Vec<u8> = percent_decode_str(given).collect();
+    given.ct_eq(token.as_bytes()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::Router;
+    use axum::body::Body;
+    use axum::routing::get;
+    use tower::ServiceExt;
+
+    fn app() -> Router {
+        Router::new()
+            .route("/view/{*path}", get(|| async { "page" }))
+            .layer(axum::middleware::from_fn_with_state(Arc::<str>::from("s3cret"), require_token))
+    }
+
+    async fn send(uri: &str, cookie: Option<&str>) -> Response {
+        let mut request = Request::builder().uri(uri);
+        if let Some(cookie) = cookie {
+            request = request.header(header::COOKIE, cookie);
+        }
+        app().oneshot(request.body(Body::empty()).unwrap()).await.unwrap()
+    }
+
+    #[test]
+    fn generated_tokens_differ() {
+        let token = generate_token();
+        assert_eq!(token.len(), 32);
+        assert_ne!(token, generate_token());
+    }
+
+    #[tokio::test]
+    async fn rejects_missing_or_wrong_token() {
+        assert_eq!(send("/view/a.md", None).await.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(send("/view/a.md?token=nope", None).await.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(send("/view/a.md", Some("marpe_token=nope")).await.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn exchanges_query_token_for_cookie() {
+        let response = send("/view/a.md?x=1&token=s3cret", None).await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(response.headers()[header::LOCATION], "/view/a.md?x=1");
+        let cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+        assert!(cookie.starts_with("marpe_token=s3cret;"), "{cookie}");
+        assert!(cookie.contains("HttpOnly"));
+    }
+
+    #[tokio::test]
+    async fn encodes_unusual_tokens() {
+        let app = Router::new()
+            .route("/", get(|| async { "index" }))
+            .layer(axum::middleware::from_fn_with_state(Arc::<str>::from("a b;c"), require_token));
+        let request = Request::builder().uri("/?token=a%20b%3Bc").body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        let cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+        assert!(cookie.starts_with("marpe_token=a%20b%3Bc;"), "{cookie}");
+    }
+
+    #[tokio::test]
+    async fn accepts_cookie() {
+        let response = send("/view/a.md", Some("theme=dark; marpe_token=s3cret")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,99 @@
+use serde::Deserialize;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr};
+use std::str::FromStr;
+
+/// The address the server listens on: `localhost` (the IPv4 loopback, the
+/// default) or any IPv4 or IPv6 address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(try_from = "String")]
+pub enum BindHost {
+    #[default]
+    Localhost,
+    Ip(IpAddr),
+}
+
+impl BindHost {
+    pub fn addr(self) -> IpAddr {
+        match self {
+            Self::Localhost => IpAddr::V4(Ipv4Addr::LOCALHOST),
+            Self::Ip(ip) => ip,
+        }
+    }
+
+    /// False if other machines may be able to connect.
+    pub fn is_loopback(self) -> bool {
+        self.addr().is_loopback()
+    }
+
+    /// Host part of the URL to open in a local browser. Wildcard addresses
+    /// are reached through `localhost`.
+    pub fn url_host(self) -> String {
+        match self {
+            Self::Ip(IpAddr::V6(ip)) if !ip.is_unspecified() => format!("[{ip}]"),
+            Self::Ip(ip) if !ip.is_unspecified() => ip.to_string(),
+            _ => "localhost".to_string(),
+        }
+    }
+}
+
+impl FromStr for BindHost {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("localhost") {
+            return Ok(Self::Localhost);
+        }
+        let ip = s.strip_prefix('[').and_then(|s| s.strip_suffix(']')).unwrap_or(s);
+        ip.parse()
+            .map(Self::Ip)
+            .map_err(|_| format!("'{s}' is not an IP address or `localhost`"))
+    }
+}
+
+impl TryFrom<String> for BindHost {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for BindHost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Localhost => f.write_str("localhost"),
+            Self::Ip(ip) => ip.fmt(f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hosts() {
+        assert_eq!("localhost".parse(), Ok(BindHost::Localhost));
+        assert_eq!("0.0.0.0".parse::<BindHost>().unwrap().addr().to_string(), "0.0.0.0");
+        assert_eq!("::1".parse::<BindHost>().unwrap().addr().to_string(), "::1");
+        assert_eq!("[fe80::1]".parse::<BindHost>().unwrap().addr().to_string(), "fe80::1");
+        assert!("example.com".parse::<BindHost>().is_err());
+        assert!("127.0.0.1:80".parse::<BindHost>().is_err());
+    }
+
+    #[test]
+    fn loopback_and_url_host() {
+        let host = |s: &str| s.parse::<BindHost>().unwrap();
+        assert!(host("localhost").is_loopback());
+        assert!(host("::1").is_loopback());
+        assert!(!host("0.0.0.0").is_loopback());
+        assert!(!host("192.168.1.5").is_loopback());
+
+        assert_eq!(host("localhost").url_host(), "localhost");
+        assert_eq!(host("0.0.0.0").url_host(), "localhost");
+        assert_eq!(host("::").url_host(), "localhost");
+        assert_eq!(host("::1").url_host(), "[::1]");
+        assert_eq!(host("192.168.1.5").url_host(), "192.168.1.5");
+    }
+}
@@ -0,0 +1,201 @@
+use percent_encoding::percent_decode_str;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+use crate::filter::has_markdown_extension;
+use crate::render::{ReferenceKind, Rendered, USER_CONTENT_PREFIX, is_relative_url, resolve_relative};
+
+/// A problem found by [`check_files`], located at a line of a source file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    pub path: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.path, self.line, self.message)
+    }
+}
+
+/// Validate relative links, heading anchors, image paths and footnote
+/// references in every rendered file. External and absolute URLs are not
+/// checked. Links to markdown files are resolved against `files` first, so
+/// their anchors can be checked, then against the disk under `root`.
+pub fn check_files(root: &Path, files: &BTreeMap<String, Rendered>) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for (path, doc) in files {
+        for reference in &doc.references {
+            let message = match reference.kind {
+                ReferenceKind::UndefinedFootnote => Some(format!("undefined footnote [^{}]", reference.target)),
+                ReferenceKind::UndefinedLabel => Some(format!("undefined link reference [{}]", reference.target)),
+                ReferenceKind::Link | ReferenceKind::Image => {
+                    check_url(root, files, path, doc, &reference.target, reference.kind == ReferenceKind::Image)
+                }
+            };
+            if let Some(message) = message {
+                diagnostics.push(Diagnostic { path: path.clone(), line: reference.line, message });
+            }
+        }
+    }
+    diagnostics
+}
+
+fn check_url(
+    root: &Path,
+    files: &BTreeMap<String, Rendered>,
+    path: &str,
+    doc: &Rendered,
+    url: &str,
+    is_image: bool,
+) -> Option<String> {
+    if let Some(fragment) = url.strip_prefix('#') {
+        return (!has_anchor(doc, fragment)).then(|| format!("no heading for anchor #{fragment}"));
+    }
+    if !is_relative_url(url) {
+        return None;
+    }
+
+    let (url_path, fragment) = match url.split_once('#') {
+        Some((p, f)) => (p, Some(f)),
+        None => (url, None),
+    };
+    let url_path = url_path.split_once('?').map_or(url_path, |(p, _)| p);
+    let what = if is_image { "missing image" } else { "broken link" };
+    let Some(resolved) = resolve_relative(path, url_path) else {
+        return Some(format!("{what} {url} (outside the root)"));
+    };
+    let target = percent_decode_str(&resolved).decode_utf8_lossy().into_owned();
+
+    if let Some(target_doc) = files.get(&target) {
+        return match fragment {
+            Some(fragment) if !has_anchor(target_doc, fragment) => {
+                Some(format!("no heading for anchor #{fragment} in {target}"))
+            }
+            _ => None,
+        };
+    }
+    if root.join(&target).exists() {
+        return None;
+    }
+    let kind = if !is_image && has_markdown_extension(&target) { "markdown file" } else { "file" };
+    Some(format!("{what} {url} ({kind} {target} not found)"))
+}
+
+fn has_anchor(doc: &Rendered, fragment: &str) -> bool {
+    let fragment = percent_decode_str(fragment).decode_utf8_lossy();
+    fragment.is_empty()
+        || doc.toc.iter().any(|entry| entry.slug == fragment)
+        || html_anchors(&doc.html).any(|anchor| anchor == fragment)
+}
+
+/// Targets of `id` and `name` attributes in rendered HTML, such as footnote
+/// definitions and raw `<a id=...>`, without the sanitizer's id prefix.
+fn html_anchors(html: &str) -> impl Iterator<Item = &str> {
+    [" id=", " name="].into_iter().flat_map(move |attr| {
+        html.match_indices(attr).filter_map(move |(start, _)| {
+            let rest = &html[start + attr.len()..];
+            let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+            let value = &rest[1..1 + rest[1..].find(quote)?];
+            Some(value.strip_prefix(USER_CONTENT_PREFIX).unwrap_or(value))
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::{RenderOptions, render_markdown};
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn setup_temp_dir(name: &str) -> PathBuf {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(format!("_scratch/check_{name}"));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn check(root: &Path, docs: &[(&str, &str)]) -> Vec<String> {
+        let files = docs
+            .iter()
+            .map(|(path, text)| (path.to_string(), render_markdown(text, path, &RenderOptions::default())))
+            .collect();
+        check_files(root, &files).iter().map(|d| d.to_string()).collect()
+    }
+
+    #[test]
+    fn accepts_valid_references() {
+        let dir = setup_temp_dir("valid");
+        fs::create_dir_all(dir.join("img")).unwrap();
+        fs::write(dir.join("img/a b.png"), "").unwrap();
+        let diagnostics = check(
+            &dir,
+            &[
+                (
+                    "README.md",
+                    "# Intro\n\n[self](#intro) [guide](docs/guide.md#setup) [ext](https://example.com)\n\n\
+                     ![pic](img/a%20b.png) text[^1]\n\n[^1]: note\n",
+                ),
+                ("docs/guide.md", "## Setup\n\n[back](../README.md)\n"),
+            ],
+        );
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+    }
+
+    #[test]
+    fn reports_broken_references_with_lines() {
+        let dir = setup_temp_dir("broken");
+        let diagnostics = check(
+            &dir,
+            &[
+                ("a.md", "# A\n\n[x](missing.md)\n[y](b.md#nope)\n![z](img/none.png)\n"),
+                ("b.md", "# B\n\n[up](../../out.md) [top](#b) [bad](#gone)\n\ntext[^nope] and [r][undefined]\n"),
+            ],
+        );
+        assert_eq!(
+            diagnostics,
+            vec![
+                "a.md:3: broken link missing.md (markdown file missing.md not found)",
+                "a.md:4: no heading for anchor #nope in b.md",
+                "a.md:5: missing image img/none.png (file img/none.png not found)",
+                "b.md:3: broken link ../../out.md (outside the root)",
+                "b.md:3: no heading for anchor #gone",
+                "b.md:5: undefined footnote [^nope]",
+                "b.md:5: undefined link reference [undefined]",
+            ]
+        );
+    }
+
+    #[test]
+    fn accepts_footnote_and_raw_html_anchors() {
+        let dir = setup_temp_dir("anchors");
+        let diagnostics = check(
+            &dir,
+            &[
+                ("a.md", "Text[^note] and [see](#note) or [raw](#here) [b](b.md#there)\n\n[^note]: A note\n\n<a id=\"here\"></a>\n"),
+                ("b.md", "<div id='there'>x</div>\n\n[missing](#nowhere)\n"),
+            ],
+        );
+        assert_eq!(diagnostics, vec!["b.md:3: no heading for anchor #nowhere"]);
+    }
+
+    #[test]
+    fn ignores_footnote_syntax_in_code() {
+        let dir = setup_temp_dir("code");
+        let diagnostics = check(&dir, &[("a.md", "```sh\ngrep \"[^a-z]\" x\n```\n\n    echo [^x]\n\n`[^y]`\n")]);
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+    }
+
+    #[test]
+    fn links_to_unserved_files_on_disk_are_fine() {
+        let dir = setup_temp_dir("disk");
+        fs::write(dir.join("notes.txt"), "").unwrap();
+        fs::write(dir.join("draft.md"), "").unwrap();
+        let diagnostics = check(&dir, &[("a.md", "[n](notes.txt) [d](draft.md#anything)\n")]);
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+    }
+}
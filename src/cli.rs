@@ -1,97 +1,452 @@
-use std::path::PathBuf;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::assets::MarkdownStyle;
+use crate::auth::generate_token;
+use crate::bind::BindHost;
+use crate::config::Config;
+use crate::render::SanitizeMode;
+use crate::state::theme_names;
+
+const CONFIG_HELP: &str = "Options not given on the command line are read from marpe.toml in DIRECTORY, \
+    then from $XDG_CONFIG_HOME/marpe/config.toml.";
+
+/// Local markdown preview server with live reload.
+#[derive(Parser)]
+#[command(name = "marpe", version, about, after_help = CONFIG_HELP, args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<CliCommand>,
+
+    /// Options for `serve`, which runs when no command is given.
+    #[command(flatten)]
+    serve: ServeArgs,
+}
+
+#[derive(Subcommand)]
+enum CliCommand {
+    /// Serve a directory with live reload (the default)
+    Serve(ServeArgs),
+    /// Write a static HTML site
+    Export(ExportArgs),
+    /// Render a single markdown file to HTML
+    Render(RenderArgs),
+    /// Check a directory for broken links and anchors
+    Check(SourceArgs),
+    /// List the available syntax highlighting themes
+    Themes,
+    /// Print a shell completion script
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+}
+
+/// Which files to read.
+#[derive(clap::Args)]
+struct SourceArgs {
+    /// Directories of markdown files, each optionally as NAME=PATH, or one markdown file [default: current directory]
+    #[arg(value_name = "DIRECTORY")]
+    roots: Vec<RootSpec>,
+    /// Only serve markdown files matching GLOB (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    include: Vec<String>,
+    /// Skip files and directories matching GLOB (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+    /// Don't skip files matched by .gitignore/.ignore
+    #[arg(long, overrides_with = "ignore")]
+    no_ignore: bool,
+    /// Skip files matched by .gitignore/.ignore, overriding `no-ignore` in marpe.toml
+    #[arg(long, overrides_with = "no_ignore")]
+    ignore: bool,
+}
+
+/// How pages look.
+#[derive(clap::Args)]
+struct PageArgs {
+    /// Syntax theme for light mode [default: InspiredGitHub]
+    #[arg(long, value_name = "THEME")]
+    syntax_theme_light: Option<String>,
+    /// Syntax theme for dark mode [default: Monokai]
+    #[arg(long, value_name = "THEME")]
+    syntax_theme_dark: Option<String>,
+    /// Raw HTML handling [default: strict]
+    #[arg(long, value_enum, value_name = "MODE")]
+    sanitize: Option<SanitizeMode>,
+    /// Default markdown style [default: github]
+    #[arg(long, value_enum)]
+    style: Option<MarkdownStyle>,
+}
+
+#[derive(clap::Args)]
+struct ServeArgs {
+    #[command(flatten)]
+    source: SourceArgs,
+    #[command(flatten)]
+    page: PageArgs,
+    /// Enable HTTPS (uses mkcert certificates)
+    #[arg(long, overrides_with = "no_tls")]
+    tls: bool,
+    /// Serve plain HTTP, overriding `tls` in marpe.toml
+    #[arg(long, overrides_with = "tls")]
+    no_tls: bool,
+    /// TLS certificate file (PEM)
+    #[arg(long, value_name = "PATH", requires = "key")]
+    cert: Option<PathBuf>,
+    /// TLS private key file (PEM)
+    #[arg(long, value_name = "PATH", requires = "cert")]
+    key: Option<PathBuf>,
+    /// Address to listen on: an IPv4/IPv6 address or `localhost` [default: localhost]
+    #[arg(long, visible_alias = "host", value_name = "ADDR")]
+    bind: Option<BindHost>,
+    /// Starting port; up to 10 ports are tried [default: 13181]
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    port: Option<u16>,
+    /// Open the browser automatically
+    #[arg(long, overrides_with = "no_open")]
+    open: bool,
+    /// Don't open the browser, overriding `open` in marpe.toml
+    #[arg(long, overrides_with = "open")]
+    no_open: bool,
+    /// Require this access token on every request
+    #[arg(long, env = "MARPE_TOKEN", hide_env_values = true, value_name = "TOKEN")]
+    token: Option<String>,
+    /// Require a random access token generated at startup
+    #[arg(long, conflicts_with = "token")]
+    generate_token: bool,
+    /// Require HTTP Basic login with a user from this htpasswd file (bcrypt or argon2)
+    #[arg(long, value_name = "FILE")]
+    htpasswd: Option<PathBuf>,
+    /// Wait until a file has been quiet this long before reloading it [default: 100]
+    #[arg(long, value_name = "MS")]
+    debounce: Option<u64>,
+}
+
+#[derive(clap::Args)]
+struct ExportArgs {
+    #[command(flatten)]
+    source: SourceArgs,
+    #[command(flatten)]
+    page: PageArgs,
+    /// Output directory [default: site]
+    #[arg(short, long, value_name = "DIR")]
+    out: Option<PathBuf>,
+}
+
+#[derive(clap::Args)]
+struct RenderArgs {
+    /// Markdown file to render, or `-` for stdin
+    #[arg(value_name = "FILE")]
+    input: PathBuf,
+    /// Write to FILE instead of stdout
+    #[arg(short, long, value_name = "FILE")]
+    out: Option<PathBuf>,
+    /// Write a complete HTML page with styles and syntax themes inlined,
+    /// instead of the bare fragment
+    #[arg(long)]
+    standalone: bool,
+    #[command(flatten)]
+    page: PageArgs,
+}
+
+impl SourceArgs {
+    fn apply(self, flags: &mut Config) -> Vec<RootSpec> {
+        flags.include = (!self.include.is_empty()).then_some(self.include);
+        flags.exclude = (!self.exclude.is_empty()).then_some(self.exclude);
+        flags.no_ignore = switch(self.no_ignore, self.ignore);
+        self.roots
+    }
+}
+
+/// The setting of a `--flag`/`--no-flag` pair, or `None` if neither was
+/// given. The flags override each other, so at most one is set.
+fn switch(on: bool, off: bool) -> Option<bool> {
+    (on || off).then_some(on)
+}
+
+/// Top-level URL segments that cannot be used as root names.
+const RESERVED_ROOT_NAMES: &[&str] = &["api", "events", "vendor"];
+
+/// A directory to serve, given as `PATH` or `NAME=PATH`. With several roots,
+/// each is served under `/<NAME>/`; the name defaults to the directory name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RootSpec {
+    pub name: Option<String>,
+    pub path: PathBuf,
+}
+
+impl FromStr for RootSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((name, path)) if !name.is_empty() && !name.contains('/') => {
+                check_root_name(name)?;
+                Ok(Self { name: Some(name.to_string()), path: PathBuf::from(path) })
+            }
+            _ => Ok(Self { name: None, path: PathBuf::from(s) }),
+        }
+    }
+}
+
+/// Root names become a URL segment, so they are limited to letters, digits,
+/// `.`, `_` and `-`.
+pub fn check_root_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || !name.chars().all(is_root_name_char) || name == "." || name == ".." {
+        return Err(format!("invalid root name '{name}': use letters, digits, '.', '_' and '-'"));
+    }
+    if RESERVED_ROOT_NAMES.contains(&name) {
+        return Err(format!("'{name}' is reserved and cannot be a root name"));
+    }
+    Ok(())
+}
+
+fn is_root_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-')
+}
+
+/// Default name for a root: its directory name with unusable characters replaced by `-`.
+pub fn default_root_name(path: &Path) -> String {
+    let name: String = path
+        .file_name()
+        .map(|n| n.to_string_lossy().chars().map(|c| if is_root_name_char(c) { c } else { '-' }).collect())
+        .unwrap_or_default();
+    if name.is_empty() { "root".to_string() } else { name }
+}
+
+impl PageArgs {
+    fn apply(self, flags: &mut Config) {
+        flags.syntax_theme_light = self.syntax_theme_light;
+        flags.syntax_theme_dark = self.syntax_theme_dark;
+        flags.sanitize = self.sanitize;
+        flags.style = self.style;
+    }
+}
+
+pub enum Command {
+    /// Serve the directory with live reload (the default).
+    Serve,
+    /// Write a static HTML site to `out`.
+    Export { out: PathBuf },
+    /// Render one file (`None` for stdin) to `out` (`None` for stdout).
+    Render { input: Option<PathBuf>, out: Option<PathBuf>, standalone: bool },
+    /// Report broken links.
+    Check,
+    /// List syntax themes.
+    Themes,
+}
 
 pub struct Args {
-    pub root: PathBuf,
+    pub command: Command,
+    /// Directories to read; never empty.
+    pub roots: Vec<RootSpec>,
     pub tls: bool,
     pub cert: Option<PathBuf>,
     pub key: Option<PathBuf>,
+    pub bind: BindHost,
     pub port: u16,
     pub syntax_theme_light: String,
     pub syntax_theme_dark: String,
     pub open: bool,
+    /// Watcher debounce window in milliseconds.
+    pub debounce: u64,
+    /// Access token required by the server, if any.
+    pub token: Option<String>,
+    /// The htpasswd file guarding each root, in the order of `roots`.
+    pub htpasswd: Vec<Option<PathBuf>>,
+    pub sanitize: SanitizeMode,
+    pub style: MarkdownStyle,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub no_ignore: bool,
 }
 
+/// Parse the command line and merge it over the config files. Usage errors
+/// print a message and exit with status 2; `completions` prints its script
+/// and exits.
 pub fn parse_args() -> Args {
-    let mut args = std::env::args().skip(1);
-    let mut root: Option<PathBuf> = None;
-    let mut tls = false;
-    let mut cert: Option<PathBuf> = None;
-    let mut key: Option<PathBuf> = None;
-    let mut port: u16 = 13181;
-    let mut syntax_theme_light = "InspiredGitHub".to_string();
-    let mut syntax_theme_dark = "Monokai".to_string();
-    let mut open = false;
-
-    while let Some(arg) = args.next() {
-        match arg.as_str() {
-            "--tls" => tls = true,
-            "--open" => open = true,
-            "--cert" => {
-                cert = Some(PathBuf::from(args.next().unwrap_or_else(|| {
-                    eprintln!("Missing path for --cert");
-                    std::process::exit(1);
-                })));
-            }
-            "--key" => {
-                key = Some(PathBuf::from(args.next().unwrap_or_else(|| {
-                    eprintln!("Missing path for --key");
-                    std::process::exit(1);
-                })));
-            }
-            "--port" => {
-                if let Some(p) = args.next() {
-                    port = p.parse().expect("Invalid port number");
-                } else {
-                    eprintln!("Missing port number");
-                    std::process::exit(1);
-                }
-            }
-            "--syntax-theme-light" => {
-                if let Some(t) = args.next() {
-                    syntax_theme_light = t;
-                } else {
-                    eprintln!("Missing theme name for --syntax-theme-light");
-                    std::process::exit(1);
-                }
-            }
-            "--syntax-theme-dark" => {
-                if let Some(t) = args.next() {
-                    syntax_theme_dark = t;
-                } else {
-                    eprintln!("Missing theme name for --syntax-theme-dark");
-                    std::process::exit(1);
-                }
-            }
-            "--help" | "-h" => {
-                eprintln!("Usage: markdown-preview [OPTIONS] [DIRECTORY]");
-                eprintln!();
-                eprintln!("Options:");
-                eprintln!("  --tls          Enable HTTPS (uses mkcert certificates)");
-                eprintln!("  --open         Open the browser automatically");
-                eprintln!("  --cert <PATH>  TLS certificate file (PEM)");
-                eprintln!("  --key <PATH>   TLS private key file (PEM)");
-                eprintln!("  --port <PORT>  Starting port (default: 13181)");
-                eprintln!("  --syntax-theme-light <THEME>  Syntax theme for light mode (default: InspiredGitHub)");
-                eprintln!("  --syntax-theme-dark <THEME>   Syntax theme for dark mode (default: Monokai)");
-                eprintln!("  -h, --help     Show this help");
-                std::process::exit(0);
-            }
-            other if !other.starts_with('-') => {
-                root = Some(PathBuf::from(other));
-            }
-            other => {
-                eprintln!("Unknown option: {other}");
-                std::process::exit(1);
-            }
+    let cli = Cli::parse();
+    // Settings given on the command line; anything left unset comes from marpe.toml.
+    let mut flags = Config::default();
+
+    let (command, roots) = match cli.command.unwrap_or(CliCommand::Serve(cli.serve)) {
+        CliCommand::Serve(serve) => {
+            serve.page.apply(&mut flags);
+            flags.tls = switch(serve.tls, serve.no_tls);
+            flags.cert = serve.cert;
+            flags.key = serve.key;
+            flags.bind = serve.bind;
+            flags.port = serve.port;
+            flags.open = switch(serve.open, serve.no_open);
+            flags.debounce = serve.debounce;
+            flags.token = serve.token;
+            flags.generate_token = serve.generate_token.then_some(true);
+            flags.htpasswd = serve.htpasswd;
+            (Command::Serve, serve.source.apply(&mut flags))
+        }
+        CliCommand::Export(export) => {
+            export.page.apply(&mut flags);
+            flags.out = export.out;
+            (Command::Export { out: PathBuf::new() }, export.source.apply(&mut flags))
+        }
+        CliCommand::Render(render) => {
+            render.page.apply(&mut flags);
+            let input = (render.input.as_os_str() != "-").then_some(render.input);
+            (Command::Render { input, out: render.out, standalone: render.standalone }, Vec::new())
+        }
+        CliCommand::Check(source) => (Command::Check, source.apply(&mut flags)),
+        CliCommand::Themes => (Command::Themes, Vec::new()),
+        CliCommand::Completions { shell } => {
+            clap_complete::generate(shell, &mut Cli::command(), "marpe", &mut std::io::stdout());
+            std::process::exit(0);
+        }
+    };
+
+    let roots = if roots.is_empty() {
+        let cwd = std::env::current_dir().expect("Failed to get current directory");
+        vec![RootSpec { name: None, path: cwd }]
+    } else {
+        roots
+    };
+
+    // Settings files are read from the first root, or the directory of a single file.
+    let load_config = |root: &RootSpec| {
+        let dir = if root.path.is_file() { root.path.parent().unwrap_or(Path::new(".")) } else { &root.path };
+        Config::load(dir).unwrap_or_else(|e| {
+            eprintln!("Invalid config: {e}");
+            std::process::exit(1);
+        })
+    };
+    // Other roots only contribute their login, unless `--htpasswd` overrides it.
+    let other_htpasswd: Vec<Option<PathBuf>> = roots[1..]
+        .iter()
+        .map(|root| flags.htpasswd.clone().or_else(|| load_config(root).htpasswd))
+        .collect();
+    let settings = flags.or(load_config(&roots[0]));
+
+    if settings.cert.is_some() != settings.key.is_some() {
+        usage_error(ErrorKind::MissingRequiredArgument, "cert and key must be provided together");
+    }
+
+    if settings.token.as_deref() == Some("") {
+        usage_error(ErrorKind::InvalidValue, "the access token must not be empty");
+    }
+
+    let syntax_theme_light = settings.syntax_theme_light.unwrap_or_else(|| "InspiredGitHub".to_string());
+    let syntax_theme_dark = settings.syntax_theme_dark.unwrap_or_else(|| "Monokai".to_string());
+    let themes = theme_names();
+    for theme in [&syntax_theme_light, &syntax_theme_dark] {
+        if !themes.contains(theme) {
+            usage_error(ErrorKind::InvalidValue, &format!("unknown syntax theme '{theme}' (see `marpe themes`)"));
         }
     }
 
-    if cert.is_some() != key.is_some() {
-        eprintln!("Error: --cert and --key must be provided together");
-        std::process::exit(1);
+    let command = match command {
+        Command::Export { .. } => Command::Export { out: settings.out.unwrap_or_else(|| PathBuf::from("site")) },
+        other => other,
+    };
+
+    Args {
+        command,
+        roots,
+        tls: settings.tls.unwrap_or(false),
+        cert: settings.cert,
+        key: settings.key,
+        bind: settings.bind.unwrap_or_default(),
+        port: settings.port.unwrap_or(13181),
+        syntax_theme_light,
+        syntax_theme_dark,
+        open: settings.open.unwrap_or(false),
+        debounce: settings.debounce.unwrap_or(100),
+        htpasswd: std::iter::once(settings.htpasswd).chain(other_htpasswd).collect(),
+        token: settings.token.or_else(|| settings.generate_token.unwrap_or(false).then(generate_token)),
+        sanitize: settings.sanitize.unwrap_or_default(),
+        style: settings.style.unwrap_or_default(),
+        include: settings.include.unwrap_or_default(),
+        exclude: settings.exclude.unwrap_or_default(),
+        no_ignore: settings.no_ignore.unwrap_or(false),
+    }
+}
+
+fn usage_error(kind: ErrorKind, message: &str) -> ! {
+    Cli::command().error(kind, message).exit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cli_definition_is_valid() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn bare_directory_means_serve() {
+        let cli = Cli::try_parse_from(["marpe", "--port", "8080", "docs"]).unwrap();
+        assert!(cli.command.is_none());
+        assert_eq!(cli.serve.port, Some(8080));
+        assert_eq!(cli.serve.bind, None);
+        assert_eq!(cli.serve.source.roots, vec![RootSpec { name: None, path: PathBuf::from("docs") }]);
+    }
+
+    #[test]
+    fn parses_subcommands() {
+        let cli = Cli::try_parse_from(["marpe", "export", "-o", "public", "--exclude", "drafts", "docs"]).unwrap();
+        let Some(CliCommand::Export(export)) = cli.command else { panic!("expected export") };
+        assert_eq!(export.out, Some(PathBuf::from("public")));
+        assert_eq!(export.source.exclude, vec!["drafts"]);
+
+        let cli = Cli::try_parse_from(["marpe", "render", "-", "--sanitize", "off", "--standalone", "-o", "a.html"]).unwrap();
+        let Some(CliCommand::Render(render)) = cli.command else { panic!("expected render") };
+        assert_eq!(render.input, PathBuf::from("-"));
+        assert_eq!(render.out, Some(PathBuf::from("a.html")));
+        assert!(render.standalone);
+        assert_eq!(render.page.sanitize, Some(SanitizeMode::Off));
     }
 
-    let root = root.unwrap_or_else(|| std::env::current_dir().expect("Failed to get current directory"));
+    #[test]
+    fn negated_flags_override_config() {
+        let serve = |args: &[&str]| Cli::try_parse_from(args).unwrap().serve;
+        let s = serve(&["marpe", "--no-open", "--no-tls", "--ignore"]);
+        assert_eq!(switch(s.open, s.no_open), Some(false));
+        assert_eq!(switch(s.tls, s.no_tls), Some(false));
+        assert_eq!(switch(s.source.no_ignore, s.source.ignore), Some(false));
+        let s = serve(&["marpe", "--no-open", "--open", "--tls"]);
+        assert_eq!(switch(s.open, s.no_open), Some(true));
+        assert_eq!(switch(s.tls, s.no_tls), Some(true));
+        assert_eq!(switch(s.source.no_ignore, s.source.ignore), None);
+    }
 
-    Args { root, tls, cert, key, port, syntax_theme_light, syntax_theme_dark, open }
+    #[test]
+    fn parses_named_roots() {
+        let cli = Cli::try_parse_from(["marpe", "api-docs=../api/docs", "../web", "./a=b"]).unwrap();
+        assert_eq!(
+            cli.serve.source.roots,
+            vec![
+                RootSpec { name: Some("api-docs".into()), path: PathBuf::from("../api/docs") },
+                RootSpec { name: None, path: PathBuf::from("../web") },
+                RootSpec { name: None, path: PathBuf::from("./a=b") },
+            ]
+        );
+        assert_eq!(default_root_name(Path::new("/src/My Notes")), "My-Notes");
+        assert_eq!(default_root_name(Path::new("/")), "root");
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        let kind = |args: &[&str]| Cli::try_parse_from(args).err().map(|e| e.kind());
+        assert_eq!(kind(&["marpe", "--port", "0"]), Some(ErrorKind::ValueValidation));
+        assert_eq!(kind(&["marpe", "--port", "http"]), Some(ErrorKind::ValueValidation));
+        assert_eq!(kind(&["marpe", "--bind", "lan"]), Some(ErrorKind::ValueValidation));
+        assert_eq!(kind(&["marpe", "api=docs"]), Some(ErrorKind::ValueValidation));
+        assert_eq!(kind(&["marpe", "a b=docs"]), Some(ErrorKind::ValueValidation));
+        assert_eq!(kind(&["marpe", "--sanitize", "loose"]), Some(ErrorKind::InvalidValue));
+        assert_eq!(kind(&["marpe", "--cert", "c.pem"]), Some(ErrorKind::MissingRequiredArgument));
+        assert_eq!(kind(&["marpe", "export", "--tls"]), Some(ErrorKind::UnknownArgument));
+    }
 }
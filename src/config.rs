@@ -0,0 +1,222 @@
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+use crate::assets::MarkdownStyle;
+use crate::bind::BindHost;
+use crate::render::SanitizeMode;
+
+/// Name of the project config file looked up in the served root.
+pub const PROJECT_CONFIG: &str = "marpe.toml";
+
+/// Settings read from `marpe.toml` files. Every field mirrors a command line
+/// flag; unset fields fall through to the next source (CLI, then project
+/// config, then user config, then built-in defaults).
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub bind: Option<BindHost>,
+    pub port: Option<u16>,
+    pub tls: Option<bool>,
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    pub open: Option<bool>,
+    pub debounce: Option<u64>,
+    pub token: Option<String>,
+    pub generate_token: Option<bool>,
+    pub htpasswd: Option<PathBuf>,
+    pub syntax_theme_light: Option<String>,
+    pub syntax_theme_dark: Option<String>,
+    pub sanitize: Option<SanitizeMode>,
+    pub style: Option<MarkdownStyle>,
+    pub include: Option<Vec<String>>,
+    pub exclude: Option<Vec<String>>,
+    pub no_ignore: Option<bool>,
+    /// Output directory for `export`.
+    pub out: Option<PathBuf>,
+}
+
+impl Config {
+    /// Load the user config and the project config in `root`, with project
+    /// settings overriding user ones. Missing files are not an error.
+    pub fn load(root: &Path) -> Result<Self, String> {
+        let user = match user_config_path() {
+            Some(path) => Self::read(&path)?,
+            None => Self::default(),
+        };
+        Ok(Self::read_project(&root.join(PROJECT_CONFIG))?.or(user))
+    }
+
+    /// Parse a project config, which comes with the files being previewed and
+    /// may not be trusted: keys that would expose the server, share a secret,
+    /// let raw HTML run scripts or send export output elsewhere are only
+    /// accepted from the command line or the user config.
+    fn read_project(path: &Path) -> Result<Self, String> {
+        let config = Self::read(path)?;
+        let user_only = [
+            ("bind", config.bind.is_some()),
+            ("token", config.token.is_some()),
+            ("generate-token", config.generate_token.is_some()),
+            ("sanitize", config.sanitize.is_some()),
+            ("out", config.out.is_some()),
+        ];
+        match user_only.iter().find(|(_, set)| *set) {
+            Some((key, _)) => Err(format!(
+                "{}: `{key}` can only be set on the command line or in the user config",
+                path.display()
+            )),
+            None => Ok(config),
+        }
+    }
+
+    /// Parse one config file. Relative paths in it are resolved against the
+    /// file's directory.
+    fn read(path: &Path) -> Result<Self, String> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(format!("{}: {e}", path.display())),
+        };
+        let mut config: Self = toml::from_str(&text).map_err(|e| format!("{}: {e}", path.display()))?;
+        let dir = path.parent().unwrap_or(Path::new("."));
+        for p in [&mut config.cert, &mut config.key, &mut config.htpasswd, &mut config.out].into_iter().flatten() {
+            *p = dir.join(&*p);
+        }
+        Ok(config)
+    }
+
+    /// Field-wise merge: values set in `self` win over `fallback`.
+    pub fn or(self, fallback: Self) -> Self {
+        Self {
+            bind: self.bind.or(fallback.bind),
+            port: self.port.or(fallback.port),
+            tls: self.tls.or(fallback.tls),
+            cert: self.cert.or(fallback.cert),
+            key: self.key.or(fallback.key),
+            open: self.open.or(fallback.open),
+            debounce: self.debounce.or(fallback.debounce),
+            token: self.token.or(fallback.token),
+            generate_token: self.generate_token.or(fallback.generate_token),
+            htpasswd: self.htpasswd.or(fallback.htpasswd),
+            syntax_theme_light: self.syntax_theme_light.or(fallback.syntax_theme_light),
+            syntax_theme_dark: self.syntax_theme_dark.or(fallback.syntax_theme_dark),
+            sanitize: self.sanitize.or(fallback.sanitize),
+            style: self.style.or(fallback.style),
+            include: self.include.or(fallback.include),
+            exclude: self.exclude.or(fallback.exclude),
+            no_ignore: self.no_ignore.or(fallback.no_ignore),
+            out: self.out.or(fallback.out),
+        }
+    }
+}
+
+/// `$XDG_CONFIG_HOME/marpe/config.toml`, falling back to `~/.config/marpe/config.toml`.
+fn user_config_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("marpe").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn write_config(name: &str, text: &str) -> PathBuf {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(format!("_scratch/config_{name}"));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(PROJECT_CONFIG), text).unwrap();
+        dir.join(PROJECT_CONFIG)
+    }
+
+    #[test]
+    fn parses_all_fields() {
+        let path = write_config(
+            "fields",
+            r#"
+            bind = "::"
+            port = 8080
+            tls = true
+            cert = "certs/cert.pem"
+            key = "/etc/key.pem"
+            open = true
+            debounce = 250
+            generate-token = true
+            htpasswd = ".htpasswd"
+            syntax-theme-light = "Solarized (light)"
+            syntax-theme-dark = "base16-ocean.dark"
+            sanitize = "off"
+            style = "gitlab"
+            include = ["docs/**"]
+            exclude = ["drafts"]
+            no-ignore = true
+            out = "public"
+            "#,
+        );
+        let config = Config::read(&path).unwrap();
+        let dir = path.parent().unwrap();
+        assert_eq!(config.bind, Some("::".parse().unwrap()));
+        assert_eq!(config.port, Some(8080));
+        assert_eq!(config.debounce, Some(250));
+        assert_eq!(config.generate_token, Some(true));
+        assert_eq!(config.htpasswd, Some(dir.join(".htpasswd")));
+        assert_eq!(config.cert, Some(dir.join("certs/cert.pem")));
+        assert_eq!(config.key, Some(PathBuf::from("/etc/key.pem")));
+        assert_eq!(config.sanitize, Some(SanitizeMode::Off));
+        assert_eq!(config.style, Some(MarkdownStyle::Gitlab));
+        assert_eq!(config.include, Some(vec!["docs/**".to_string()]));
+        assert_eq!(config.no_ignore, Some(true));
+        assert_eq!(config.out, Some(dir.join("public")));
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        let path = write_config("unknown", "port = 1\nprot = 2\n");
+        let err = Config::read(&path).unwrap_err();
+        assert!(err.contains("unknown field `prot`"), "{err}");
+    }
+
+    #[test]
+    fn rejects_bad_values() {
+        let path = write_config("bad_value", "sanitize = \"loose\"\n");
+        assert!(Config::read(&path).is_err());
+        let path = write_config("bad_bind", "bind = \"lan\"\n");
+        assert!(Config::read(&path).is_err());
+    }
+
+    #[test]
+    fn project_config_cannot_expose_the_server() {
+        let texts = [
+            "bind = \"0.0.0.0\"\n",
+            "token = \"s3cret\"\n",
+            "generate-token = true\n",
+            "sanitize = \"off\"\n",
+            "out = \"../../elsewhere\"\n",
+        ];
+        for text in texts {
+            let path = write_config("user_only", text);
+            let err = Config::read_project(&path).unwrap_err();
+            assert!(err.contains("can only be set on the command line or in the user config"), "{err}");
+            assert!(Config::read(&path).is_ok());
+        }
+        let path = write_config("project_ok", "port = 8080\n");
+        assert_eq!(Config::read_project(&path).unwrap().port, Some(8080));
+    }
+
+    #[test]
+    fn missing_file_is_empty() {
+        let config = Config::read(Path::new("_scratch/config_none/marpe.toml")).unwrap();
+        assert_eq!(config, Config::default());
+    }
+
+    #[test]
+    fn earlier_source_wins() {
+        let project = Config { port: Some(1), ..Default::default() };
+        let user = Config { port: Some(2), open: Some(true), ..Default::default() };
+        let merged = project.or(user);
+        assert_eq!(merged.port, Some(1));
+        assert_eq!(merged.open, Some(true));
+    }
+}
@@ -4,30 +4,30 @@ use std::path::Path;
 use tracing::{info, warn};
 use walkdir::WalkDir;
 
-use crate::render::render_markdown;
-
-/// Returns true if path should be skipped (hidden dirs, node_modules)
-fn should_skip(path: &Path) -> bool {
-    path.components().any(|c| {
-        let s = c.as_os_str().to_string_lossy();
-        s.starts_with('.') || s == "node_modules"
-    })
+use crate::filter::FileFilter;
+use crate::render::{RenderOptions, Rendered, render_markdown};
+
+/// Walk `root` directory, find all markdown files accepted by `filter`, render them.
+/// Returns a map of relative path (string) -> rendered document.
+pub fn discover_and_render(root: &Path, options: &RenderOptions, filter: &FileFilter) -> BTreeMap<String, Rendered> {
+    discover_under(root, root, options, filter)
 }
 
-/// Walk `root` directory, find all .md files, render them.
-/// Returns a map of relative path (string) -> rendered HTML.
-pub fn discover_and_render(root: &Path) -> BTreeMap<String, String> {
-    let entries: Vec<_> = WalkDir::new(root)
+/// Like [`discover_and_render`], but only walks `dir`, a directory under
+/// `root`. Paths in the result are still relative to `root`.
+pub fn discover_under(root: &Path, dir: &Path, options: &RenderOptions, filter: &FileFilter) -> BTreeMap<String, Rendered> {
+    let entries: Vec<_> = WalkDir::new(dir)
         .into_iter()
         .filter_entry(|entry| {
             let path = entry.path();
             let relative = path.strip_prefix(root).unwrap_or(path);
-            !should_skip(relative)
+            entry.depth() == 0 || !entry.file_type().is_dir() || !filter.skip_dir(relative)
         })
         .filter_map(|e| e.ok())
         .filter(|entry| {
             let path = entry.path();
-            path.is_file() && path.extension().and_then(|e| e.to_str()) == Some("md")
+            let relative = path.strip_prefix(root).unwrap_or(path);
+            path.is_file() && filter.accepts(relative)
         })
         .collect();
 
@@ -39,9 +39,9 @@ pub fn discover_and_render(root: &Path) -> BTreeMap<String, String> {
             let rel_str = relative.to_string_lossy().to_string();
             match std::fs::read_to_string(path) {
                 Ok(content) => {
-                    let html = render_markdown(&content);
+                    let rendered = render_markdown(&content, &rel_str, options);
                     info!(path = %rel_str, "Rendered markdown file");
-                    Some((rel_str, html))
+                    Some((rel_str, rendered))
                 }
                 Err(e) => {
                     warn!(path = %rel_str, error = %e, "Failed to read markdown file");
@@ -73,7 +73,7 @@ mod tests {
         fs::write(dir.join("notes.md"), "some notes").unwrap();
         fs::write(dir.join("ignore.txt"), "not markdown").unwrap();
 
-        let files = discover_and_render(&dir);
+        let files = discover_and_render(&dir, &RenderOptions::default(), &FileFilter::default());
         assert_eq!(files.len(), 2);
         assert!(files.contains_key("README.md"));
         assert!(files.contains_key("notes.md"));
@@ -86,7 +86,7 @@ mod tests {
         fs::create_dir_all(dir.join("docs/guide")).unwrap();
         fs::write(dir.join("docs/guide/intro.md"), "# Intro").unwrap();
 
-        let files = discover_and_render(&dir);
+        let files = discover_and_render(&dir, &RenderOptions::default(), &FileFilter::default());
         assert!(files.contains_key("docs/guide/intro.md"));
     }
 
@@ -97,7 +97,7 @@ mod tests {
         fs::write(dir.join(".git/info.md"), "hidden").unwrap();
         fs::write(dir.join("visible.md"), "shown").unwrap();
 
-        let files = discover_and_render(&dir);
+        let files = discover_and_render(&dir, &RenderOptions::default(), &FileFilter::default());
         assert_eq!(files.len(), 1);
         assert!(files.contains_key("visible.md"));
     }
@@ -109,7 +109,7 @@ mod tests {
         fs::write(dir.join("node_modules/pkg/README.md"), "npm").unwrap();
         fs::write(dir.join("top.md"), "top").unwrap();
 
-        let files = discover_and_render(&dir);
+        let files = discover_and_render(&dir, &RenderOptions::default(), &FileFilter::default());
         assert_eq!(files.len(), 1);
         assert!(files.contains_key("top.md"));
     }
@@ -119,15 +119,57 @@ mod tests {
         let dir = setup_temp_dir("renders");
         fs::write(dir.join("test.md"), "**bold**").unwrap();
 
-        let files = discover_and_render(&dir);
-        let html = files.get("test.md").unwrap();
+        let files = discover_and_render(&dir, &RenderOptions::default(), &FileFilter::default());
+        let html = &files.get("test.md").unwrap().html;
         assert!(html.contains("<strong>bold</strong>"));
     }
 
+    #[test]
+    fn discovers_other_markdown_extensions() {
+        let dir = setup_temp_dir("extensions");
+        fs::write(dir.join("a.markdown"), "a").unwrap();
+        fs::write(dir.join("b.mdx"), "b").unwrap();
+        fs::write(dir.join("c.mdown"), "c").unwrap();
+
+        let files = discover_and_render(&dir, &RenderOptions::default(), &FileFilter::default());
+        assert_eq!(files.keys().collect::<Vec<_>>(), vec!["a.markdown", "b.mdx", "c.mdown"]);
+    }
+
+    #[test]
+    fn applies_include_and_exclude_globs() {
+        let dir = setup_temp_dir("globs");
+        fs::create_dir_all(dir.join("docs/drafts")).unwrap();
+        fs::write(dir.join("docs/guide.md"), "guide").unwrap();
+        fs::write(dir.join("docs/drafts/wip.md"), "wip").unwrap();
+        fs::write(dir.join("README.md"), "readme").unwrap();
+
+        let filter = FileFilter::new(&["docs/**".to_string()], &["drafts".to_string()]).unwrap();
+        let files = discover_and_render(&dir, &RenderOptions::default(), &filter);
+        assert_eq!(files.keys().collect::<Vec<_>>(), vec!["docs/guide.md"]);
+    }
+
+    #[test]
+    fn respects_gitignore_when_enabled() {
+        let dir = setup_temp_dir("gitignore");
+        fs::create_dir_all(dir.join("target/doc")).unwrap();
+        fs::write(dir.join(".gitignore"), "target/\n*.gen.md\n!keep.gen.md\n").unwrap();
+        fs::write(dir.join("target/doc/api.md"), "generated").unwrap();
+        fs::write(dir.join("a.gen.md"), "generated").unwrap();
+        fs::write(dir.join("keep.gen.md"), "kept").unwrap();
+        fs::write(dir.join("notes.md"), "authored").unwrap();
+
+        let filter = FileFilter::default().with_ignore_files(&dir);
+        let files = discover_and_render(&dir, &RenderOptions::default(), &filter);
+        assert_eq!(files.keys().collect::<Vec<_>>(), vec!["keep.gen.md", "notes.md"]);
+
+        let files = discover_and_render(&dir, &RenderOptions::default(), &FileFilter::default());
+        assert_eq!(files.len(), 4);
+    }
+
     #[test]
     fn empty_directory() {
         let dir = setup_temp_dir("empty");
-        let files = discover_and_render(&dir);
+        let files = discover_and_render(&dir, &RenderOptions::default(), &FileFilter::default());
         assert!(files.is_empty());
     }
 }
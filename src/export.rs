@@ -0,0 +1,353 @@
+use percent_encoding::{percent_decode_str, utf8_percent_encode};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::io;
+use std::path::Path;
+use tracing::{info, warn};
+
+use crate::assets::{ExportSlots, MarkdownStyle, PageShell, VENDOR_FILES};
+use crate::discovery::discover_and_render;
+use crate::filter::{FileFilter, should_skip};
+use crate::render::{LinkStyle, PATH_SEGMENT, RenderOptions, Rendered, SanitizeMode, TocEntry, escape_html};
+use crate::state::syntax_css;
+use crate::tree::{TreeNode, build_tree};
+
+/// Directory inside the export holding the vendored renderer files.
+const ASSET_DIR: &str = "_assets/";
+
+pub struct ExportOptions<'a> {
+    pub filter: &'a FileFilter,
+    pub sanitize: SanitizeMode,
+    pub syntax_theme_light: &'a str,
+    pub syntax_theme_dark: &'a str,
+    pub style: MarkdownStyle,
+}
+
+/// Render every markdown file under `root` into a static site in `out`:
+/// one `.html` page per document, the files they reference, and the vendored
+/// assets. The result works from `file://` without a server.
+/// Returns the number of pages written.
+pub fn export_site(root: &Path, out: &Path, options: &ExportOptions) -> io::Result<usize> {
+    let render_options = RenderOptions { sanitize: options.sanitize, links: LinkStyle::Relative, ..Default::default() };
+    let files = discover_and_render(root, &render_options, options.filter);
+    let (css_light, css_dark) = syntax_css(options.syntax_theme_light, options.syntax_theme_dark);
+    let shell = PageShell::new(options.style);
+
+    let paths: Vec<String> = files.keys().cloned().collect();
+    // `a.md` and `a.mdx` both map to `a.html`; links to either are rewritten
+    // to that page, so there is no way to tell them apart.
+    let mut pages: HashMap<String, &str> = HashMap::new();
+    for path in &paths {
+        if let Some(other) = pages.insert(page_path(path), path) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{other} and {path} would both be exported as {}", page_path(path)),
+            ));
+        }
+    }
+    fs::create_dir_all(out)?;
+    let tree = build_tree(&paths);
+
+    for (path, doc) in &files {
+        let to_root = "../".repeat(path.matches('/').count());
+        let asset_base = format!("{to_root}{ASSET_DIR}");
+        let mut sidebar = String::new();
+        sidebar_html(&tree, path, &to_root, &mut sidebar);
+        let slots = ExportSlots { base: "", asset_base: &asset_base, sidebar: &sidebar, toc: &toc_html(&doc.toc) };
+        let title = escape_html(doc.title().unwrap_or(path));
+        let page = shell.render_export(&slots, &title, &doc.html, &css_light, &css_dark);
+        write_file(&out.join(page_path(path)), page.as_bytes())?;
+    }
+
+    let assets: BTreeSet<&String> = files.values().flat_map(|doc| &doc.assets).collect();
+    for asset in assets {
+        copy_asset(root, out, asset)?;
+    }
+
+    write_assets(out)?;
+
+    if !pages.contains_key("index.html") {
+        let index = match paths.iter().find(|p| *p == "README.md").or(paths.first()) {
+            Some(landing) => redirect_page(&page_url(landing)),
+            None => {
+                let slots = ExportSlots { base: "", asset_base: ASSET_DIR, sidebar: "", toc: "" };
+                shell.render_export(
+                    &slots,
+                    "No files",
+                    "<p>No markdown files found in this directory.</p>",
+                    &css_light,
+                    &css_dark,
+                )
+            }
+        };
+        write_file(&out.join("index.html"), index.as_bytes())?;
+    }
+
+    info!(count = files.len(), out = %out.display(), "Exported static site");
+    Ok(files.len())
+}
+
+/// Landing page for an export of several roots, each exported by
+/// [`export_site`] into `out/<name>/`: links to each root's site.
+pub fn export_roots_index(
+    out: &Path,
+    names: &[String],
+    syntax_theme_light: &str,
+    syntax_theme_dark: &str,
+    style: MarkdownStyle,
+) -> io::Result<()> {
+    let (css_light, css_dark) = syntax_css(syntax_theme_light, syntax_theme_dark);
+    let links: String = names
+        .iter()
+        .map(|name| format!(r#"<li><a href="{}/index.html">{}</a></li>"#, encode_path(name), escape_html(name)))
+        .collect();
+    // The page needs the vendored stylesheets; every root's site has a copy.
+    let asset_base = names.first().map_or_else(|| ASSET_DIR.to_string(), |name| format!("{}/{ASSET_DIR}", encode_path(name)));
+    let slots = ExportSlots { base: "", asset_base: &asset_base, sidebar: &links, toc: "" };
+    let content = format!("<h1>Contents</h1>\n<ul>{links}</ul>");
+    let page = PageShell::new(style).render_export(&slots, "Contents", &content, &css_light, &css_dark);
+    write_file(&out.join("index.html"), page.as_bytes())
+}
+
+/// Write the vendored renderer files (math styles and fonts, mermaid) into
+/// `dir/_assets/`, where exported and standalone pages load them from.
+pub fn write_assets(dir: &Path) -> io::Result<()> {
+    for (name, _, body) in VENDOR_FILES {
+        write_file(&dir.join(ASSET_DIR).join(name), body)?;
+    }
+    Ok(())
+}
+
+/// A complete page for one rendered document, outside of any site: no
+/// sidebar, and the document's table of contents prebuilt. Math and diagrams
+/// need the files [`write_assets`] puts next to the page.
+pub fn standalone_page(
+    doc: &Rendered,
+    title: &str,
+    syntax_theme_light: &str,
+    syntax_theme_dark: &str,
+    style: MarkdownStyle,
+) -> String {
+    let (css_light, css_dark) = syntax_css(syntax_theme_light, syntax_theme_dark);
+    let slots = ExportSlots { base: "", asset_base: ASSET_DIR, sidebar: "", toc: &toc_html(&doc.toc) };
+    let title = escape_html(doc.title().unwrap_or(title));
+    PageShell::new(style).render_export(&slots, &title, &doc.html, &css_light, &css_dark)
+}
+
+/// Output path of the page for a markdown file: `docs/a.md` -> `docs/a.html`.
+fn page_path(path: &str) -> String {
+    let stem = path.rsplit_once('.').map_or(path, |(stem, _)| stem);
+    format!("{stem}.html")
+}
+
+/// Relative URL of the page for a markdown file.
+fn page_url(path: &str) -> String {
+    encode_path(&page_path(path))
+}
+
+fn encode_path(path: &str) -> String {
+    path.split('/')
+        .map(|s| utf8_percent_encode(s, PATH_SEGMENT).to_string())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Sidebar markup matching what `app.js` builds from `/api/files?tree=true`.
+fn sidebar_html(nodes: &[TreeNode], current: &str, to_root: &str, out: &mut String) {
+    for node in nodes {
+        match node {
+            TreeNode::Dir { name, path, count, children } => {
+                out.push_str(&format!(
+                    r#"<li class="dir" data-path="{}"><button class="dir-toggle">{}<span class="count">{count}</span></button><ul>"#,
+                    escape_html(path),
+                    escape_html(name),
+                ));
+                sidebar_html(children, current, to_root, out);
+                out.push_str("</ul></li>");
+            }
+            TreeNode::File { name, path } => {
+                let active = if path == current { r#" class="active""# } else { "" };
+                out.push_str(&format!(
+                    r#"<li><a href="{to_root}{}" title="{}"{active}>{}</a></li>"#,
+                    page_url(path),
+                    escape_html(path),
+                    escape_html(name),
+                ));
+            }
+        }
+    }
+}
+
+/// Table of contents markup matching what `app.js` builds from `/api/toc`.
+fn toc_html(toc: &[TocEntry]) -> String {
+    let min_level = toc.iter().map(|e| e.level).min().unwrap_or(1);
+    toc.iter()
+        .map(|entry| {
+            let padding = 8 + (entry.level - min_level) as u32 * 12;
+            format!(
+                r##"<li><a href="#{}" data-slug="{}" style="padding-left: {padding}px">{}</a></li>"##,
+                utf8_percent_encode(&entry.slug, PATH_SEGMENT),
+                escape_html(&entry.slug),
+                escape_html(&entry.text),
+            )
+        })
+        .collect()
+}
+
+fn redirect_page(url: &str) -> String {
+    let url = escape_html(url);
+    format!(
+        "<!DOCTYPE html>\n<meta charset=\"UTF-8\">\n<meta http-equiv=\"refresh\" content=\"0; url={url}\">\n<a href=\"{url}\">{url}</a>\n"
+    )
+}
+
+/// Copy a file referenced by a document into the export, keeping its
+/// relative path. References outside the root or into skipped directories
+/// are left out.
+fn copy_asset(root: &Path, out: &Path, asset: &str) -> io::Result<()> {
+    let decoded = percent_decode_str(asset).decode_utf8_lossy();
+    let relative = Path::new(decoded.as_ref());
+    if should_skip(relative) {
+        return Ok(());
+    }
+    let source = match root.join(relative).canonicalize() {
+        Ok(source) if source.starts_with(root) && source.is_file() => source,
+        _ => {
+            warn!(path = %decoded, "Skipping missing referenced file");
+            return Ok(());
+        }
+    };
+    let dest = out.join(relative);
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::copy(source, dest)?;
+    Ok(())
+}
+
+fn write_file(path: &Path, contents: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::render_markdown;
+    use std::path::PathBuf;
+
+    fn export(root: &Path, out: &Path) -> io::Result<usize> {
+        let options = ExportOptions {
+            filter: &FileFilter::default(),
+            sanitize: SanitizeMode::Strict,
+            syntax_theme_light: "InspiredGitHub",
+            syntax_theme_dark: "Monokai",
+            style: MarkdownStyle::default(),
+        };
+        export_site(root, out, &options)
+    }
+
+    fn setup_temp_dir(name: &str) -> (PathBuf, PathBuf) {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(format!("_scratch/export_{name}"));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("src")).unwrap();
+        (dir.join("src").canonicalize().unwrap(), dir.join("out"))
+    }
+
+    #[test]
+    fn roots_index_links_each_site() {
+        let (_, out) = setup_temp_dir("roots");
+        let names = vec!["api".to_string(), "guide".to_string()];
+        export_roots_index(&out, &names, "InspiredGitHub", "Monokai", MarkdownStyle::default()).unwrap();
+        let index = fs::read_to_string(out.join("index.html")).unwrap();
+        assert!(index.contains(r#"<a href="api/index.html">api</a>"#), "{index}");
+        assert!(index.contains(r#"<a href="guide/index.html">guide</a>"#));
+        assert!(index.contains("api/_assets/math/styles.css"));
+    }
+
+    #[test]
+    fn writes_pages_assets_and_index() {
+        let (root, out) = setup_temp_dir("site");
+        fs::create_dir_all(root.join("docs/img")).unwrap();
+        fs::write(root.join("README.md"), "# Home\n\nSee [guide](docs/guide.md#setup).").unwrap();
+        fs::write(root.join("docs/guide.md"), "## Setup\n\n![shot](img/shot.png)").unwrap();
+        fs::write(root.join("docs/img/shot.png"), b"png").unwrap();
+
+        assert_eq!(export(&root, &out).unwrap(), 2);
+
+        let home = fs::read_to_string(out.join("README.html")).unwrap();
+        assert!(home.contains(r#"href="docs/guide.html#setup""#));
+        assert!(home.contains(r#"data-mode="static""#));
+        assert!(home.contains(r#"<a href="README.html" title="README.md" class="active">"#));
+
+        let guide = fs::read_to_string(out.join("docs/guide.html")).unwrap();
+        assert!(guide.contains(r#"href="../_assets/math/styles.css""#));
+        assert!(guide.contains(r#"<a href="../README.html" title="README.md">"#));
+        assert!(guide.contains(r##"<a href="#setup" data-slug="setup""##));
+
+        assert_eq!(fs::read(out.join("docs/img/shot.png")).unwrap(), b"png");
+        assert!(out.join("_assets/mermaid.min.js").is_file());
+        assert!(fs::read_to_string(out.join("index.html")).unwrap().contains("url=README.html"));
+    }
+
+    #[test]
+    fn skips_assets_outside_root() {
+        let (root, out) = setup_temp_dir("outside");
+        fs::write(root.join("../secret.txt"), "secret").unwrap();
+        fs::write(root.join("a.md"), "[s](../secret.txt)").unwrap();
+
+        export(&root, &out).unwrap();
+        assert!(!out.join("secret.txt").exists());
+        assert!(fs::read_to_string(out.join("index.html")).unwrap().contains("url=a.html"));
+    }
+
+    #[test]
+    fn rejects_sources_sharing_a_page() {
+        let (root, out) = setup_temp_dir("clash");
+        fs::write(root.join("a.md"), "# A").unwrap();
+        fs::write(root.join("a.markdown"), "# Also A").unwrap();
+
+        let err = export(&root, &out).unwrap_err();
+        assert!(err.to_string().contains("a.markdown and a.md would both be exported as a.html"), "{err}");
+        assert!(!out.exists());
+    }
+
+    #[test]
+    fn standalone_page_inlines_themes_and_toc() {
+        let options = RenderOptions { links: LinkStyle::Relative, ..Default::default() };
+        let doc = render_markdown("---\ntitle: Notes\n---\n## Usage\n\n```rust\nfn main() {}\n```\n", "notes.md", &options);
+        let page = standalone_page(&doc, "notes.md", "InspiredGitHub", "Monokai", MarkdownStyle::Gitlab);
+        assert!(page.contains("<title>Notes"));
+        assert!(page.contains(".theme-light .comment"));
+        assert!(page.contains(".theme-dark .comment"));
+        assert!(page.contains(r##"<a href="#usage" data-slug="usage""##));
+        assert!(page.contains("style-gitlab"));
+    }
+
+    #[test]
+    fn standalone_page_finds_math_and_mermaid_assets() {
+        let (_, out) = setup_temp_dir("standalone");
+        let doc = render_markdown("$x^2$\n\n```mermaid\ngraph TD; A-->B\n```\n", "notes.md", &RenderOptions::default());
+        let page = standalone_page(&doc, "notes.md", "InspiredGitHub", "Monokai", MarkdownStyle::default());
+        assert!(page.contains("<math") && page.contains(r#"<pre class="mermaid">"#));
+        assert!(page.contains(r#"href="_assets/math/styles.css""#));
+        assert!(page.contains(r#"data-asset-base="_assets/""#));
+
+        write_assets(&out).unwrap();
+        assert!(out.join("_assets/math/styles.css").is_file());
+        assert!(out.join("_assets/math/font/latinmodern-math.woff2").is_file());
+        assert!(out.join("_assets/mermaid.min.js").is_file());
+    }
+
+    #[test]
+    fn sidebar_marks_active_file_and_counts_folders() {
+        let tree = build_tree(&["d/x y.md".to_string(), "z.md".to_string()]);
+        let mut html = String::new();
+        sidebar_html(&tree, "z.md", "", &mut html);
+        assert!(html.contains(r#"<li class="dir" data-path="d"><button class="dir-toggle">d<span class="count">1</span>"#));
+        assert!(html.contains(r#"href="d/x%20y.html""#));
+        assert!(html.contains(r#"<a href="z.html" title="z.md" class="active">"#));
+    }
+}
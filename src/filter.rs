@@ -0,0 +1,183 @@
+use glob::{MatchOptions, Pattern, PatternError};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::gitignore::IgnoreRules;
+
+/// File extensions treated as markdown, compared case-insensitively.
+pub const MARKDOWN_EXTENSIONS: &[&str] = &["md", "markdown", "mdx", "mdown"];
+
+/// `*` and `?` stop at `/`; only `**` crosses directories.
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// Returns true if path should be skipped (hidden dirs, node_modules)
+pub fn should_skip(path: &Path) -> bool {
+    path.components().any(|c| {
+        let s = c.as_os_str().to_string_lossy();
+        s.starts_with('.') || s == "node_modules"
+    })
+}
+
+/// True if the path or URL ends in one of [`MARKDOWN_EXTENSIONS`].
+pub fn has_markdown_extension(path: &str) -> bool {
+    path.rsplit_once('.').is_some_and(|(stem, ext)| {
+        !stem.is_empty() && !stem.ends_with('/') && MARKDOWN_EXTENSIONS.iter().any(|m| ext.eq_ignore_ascii_case(m))
+    })
+}
+
+/// Decides which files under the root are served, shared by discovery and
+/// the watcher. Paths are relative to the root.
+///
+/// A glob without a `/` matches any single path component (so `drafts` or
+/// `*.draft.md` apply at every depth); a glob with a `/` matches the whole
+/// relative path. Excludes also match against parent directories.
+#[derive(Debug, Clone, Default)]
+pub struct FileFilter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+    ignore_rules: Option<Arc<IgnoreRules>>,
+    single_file: Option<PathBuf>,
+}
+
+impl FileFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self, PatternError> {
+        let compile = |globs: &[String]| globs.iter().map(|g| Pattern::new(g)).collect::<Result<Vec<_>, _>>();
+        Ok(Self { include: compile(include)?, exclude: compile(exclude)?, ignore_rules: None, single_file: None })
+    }
+
+    /// Also skip paths matched by `.gitignore`/`.ignore` files under `root`
+    /// and the global git excludes.
+    pub fn with_ignore_files(mut self, root: &Path) -> Self {
+        self.ignore_rules = Some(Arc::new(IgnoreRules::new(root)));
+        self
+    }
+
+    /// Serve only `relative`, a file directly in the root, whatever the other
+    /// rules say. No directory is walked.
+    pub fn with_single_file(mut self, relative: &Path) -> Self {
+        self.single_file = Some(relative.to_path_buf());
+        self
+    }
+
+    /// The one file served in single-file mode.
+    pub fn single_file(&self) -> Option<&Path> {
+        self.single_file.as_deref()
+    }
+
+    /// Re-read ignore files after one of them changed on disk.
+    pub fn reload_ignore_files(&self) {
+        if let Some(rules) = &self.ignore_rules {
+            rules.reload();
+        }
+    }
+
+    /// True if the walk should not descend into this directory.
+    pub fn skip_dir(&self, relative: &Path) -> bool {
+        self.single_file.is_some()
+            || should_skip(relative)
+            || self.exclude.iter().any(|p| matches(p, relative))
+            || self.ignore_rules.as_ref().is_some_and(|rules| rules.is_ignored(relative, true))
+    }
+
+    /// True if `relative` is a markdown file that should be served.
+    pub fn accepts(&self, relative: &Path) -> bool {
+        if let Some(file) = &self.single_file {
+            return relative == file;
+        }
+        if should_skip(relative) || !has_markdown_extension(&relative.to_string_lossy()) {
+            return false;
+        }
+        if !self.include.is_empty() && !self.include.iter().any(|p| matches(p, relative)) {
+            return false;
+        }
+        if relative.ancestors().any(|dir| !dir.as_os_str().is_empty() && self.exclude.iter().any(|p| matches(p, dir))) {
+            return false;
+        }
+        !self.ignore_rules.as_ref().is_some_and(|rules| rules.is_ignored(relative, false))
+    }
+}
+
+fn matches(pattern: &Pattern, relative: &Path) -> bool {
+    if pattern.as_str().contains('/') {
+        pattern.matches_path_with(relative, MATCH_OPTIONS)
+    } else {
+        relative.file_name().is_some_and(|name| pattern.matches_with(&name.to_string_lossy(), MATCH_OPTIONS))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(include: &[&str], exclude: &[&str]) -> FileFilter {
+        let owned = |globs: &[&str]| globs.iter().map(|g| g.to_string()).collect::<Vec<_>>();
+        FileFilter::new(&owned(include), &owned(exclude)).unwrap()
+    }
+
+    #[test]
+    fn accepts_all_markdown_extensions() {
+        let f = FileFilter::default();
+        for name in ["a.md", "b.markdown", "c.mdx", "d.mdown", "E.MD"] {
+            assert!(f.accepts(Path::new(name)), "{name}");
+        }
+        assert!(!f.accepts(Path::new("notes.txt")));
+        assert!(!f.accepts(Path::new("md")));
+    }
+
+    #[test]
+    fn skips_hidden_and_node_modules() {
+        let f = FileFilter::default();
+        assert!(!f.accepts(Path::new(".git/info.md")));
+        assert!(!f.accepts(Path::new("node_modules/pkg/README.md")));
+        assert!(f.skip_dir(Path::new("node_modules")));
+    }
+
+    #[test]
+    fn include_limits_files() {
+        let f = filter(&["docs/**/*.md"], &[]);
+        assert!(f.accepts(Path::new("docs/a.md")));
+        assert!(f.accepts(Path::new("docs/guide/b.md")));
+        assert!(!f.accepts(Path::new("README.md")));
+    }
+
+    #[test]
+    fn bare_globs_match_names_at_any_depth() {
+        let f = filter(&[], &["*.draft.md", "drafts"]);
+        assert!(!f.accepts(Path::new("a/b/post.draft.md")));
+        assert!(!f.accepts(Path::new("blog/drafts/post.md")));
+        assert!(f.skip_dir(Path::new("blog/drafts")));
+        assert!(f.accepts(Path::new("blog/post.md")));
+    }
+
+    #[test]
+    fn path_globs_match_from_root() {
+        let f = filter(&[], &["archive/*"]);
+        assert!(!f.accepts(Path::new("archive/old.md")));
+        assert!(!f.accepts(Path::new("archive/2020/old.md")));
+        assert!(f.accepts(Path::new("docs/archive/new.md")));
+    }
+
+    #[test]
+    fn single_file_accepts_only_that_file() {
+        let f = filter(&[], &["*.txt"]).with_single_file(Path::new("NOTES.txt"));
+        assert!(f.accepts(Path::new("NOTES.txt")));
+        assert!(!f.accepts(Path::new("README.md")));
+        assert!(f.skip_dir(Path::new("docs")));
+    }
+
+    #[test]
+    fn rejects_invalid_globs() {
+        assert!(FileFilter::new(&["[".to_string()], &[]).is_err());
+    }
+
+    #[test]
+    fn recognises_markdown_urls() {
+        assert!(has_markdown_extension("../guide/setup.markdown"));
+        assert!(!has_markdown_extension("img/a.png"));
+        assert!(!has_markdown_extension("dir/.md"));
+    }
+}
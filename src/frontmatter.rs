@@ -0,0 +1,102 @@
+use pulldown_cmark::MetadataBlockKind;
+use serde_json::{Map, Value};
+
+use crate::render::escape_html;
+
+/// Parse a YAML (`---`) or TOML (`+++`) front matter block into a JSON value.
+/// An empty block parses to `Value::Null`.
+pub fn parse(kind: MetadataBlockKind, text: &str) -> Result<Value, String> {
+    match kind {
+        MetadataBlockKind::YamlStyle => serde_yaml::from_str(text).map_err(|e| e.to_string()),
+        MetadataBlockKind::PlusesStyle => text
+            .parse::<toml::Table>()
+            .map(|table| toml_to_json(toml::Value::Table(table)))
+            .map_err(|e| e.message().to_string()),
+    }
+}
+
+/// TOML dates have no JSON equivalent; they become their RFC 3339 string form.
+fn toml_to_json(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => Value::from(i),
+        toml::Value::Float(f) => Value::from(f),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(d) => Value::String(d.to_string()),
+        toml::Value::Array(items) => Value::Array(items.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(table) => {
+            Value::Object(table.into_iter().map(|(k, v)| (k, toml_to_json(v))).collect::<Map<_, _>>())
+        }
+    }
+}
+
+/// Metadata card shown above the document: one row per top-level key, with
+/// lists of plain values shown as tags.
+pub fn card_html(meta: &Value) -> String {
+    let Value::Object(fields) = meta else {
+        return format!("<div class=\"front-matter\"><code>{}</code></div>\n", escape_html(&meta.to_string()));
+    };
+    let mut out = String::from("<div class=\"front-matter\"><dl>");
+    for (key, value) in fields {
+        out.push_str(&format!("<dt>{}</dt><dd>{}</dd>", escape_html(key), value_html(value)));
+    }
+    out.push_str("</dl></div>\n");
+    out
+}
+
+/// Shown in place of the card when the front matter does not parse.
+pub fn error_html(message: &str) -> String {
+    format!(
+        "<div class=\"front-matter front-matter-error\">Invalid front matter: {}</div>\n",
+        escape_html(message)
+    )
+}
+
+fn value_html(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => escape_html(s),
+        Value::Array(items) if items.iter().all(is_scalar) => items
+            .iter()
+            .map(|item| format!("<span class=\"tag\">{}</span>", value_html(item)))
+            .collect(),
+        Value::Array(_) | Value::Object(_) => format!("<code>{}</code>", escape_html(&value.to_string())),
+        other => other.to_string(),
+    }
+}
+
+fn is_scalar(value: &Value) -> bool {
+    !matches!(value, Value::Array(_) | Value::Object(_))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parses_yaml() {
+        let meta = parse(MetadataBlockKind::YamlStyle, "title: Notes\ntags: [a, b]\ndraft: true\n").unwrap();
+        assert_eq!(meta, json!({ "title": "Notes", "tags": ["a", "b"], "draft": true }));
+    }
+
+    #[test]
+    fn parses_toml_with_dates() {
+        let meta = parse(MetadataBlockKind::PlusesStyle, "title = \"Notes\"\ndate = 2024-05-01\n[extra]\nn = 3\n").unwrap();
+        assert_eq!(meta, json!({ "title": "Notes", "date": "2024-05-01", "extra": { "n": 3 } }));
+    }
+
+    #[test]
+    fn reports_invalid_input() {
+        assert!(parse(MetadataBlockKind::YamlStyle, "title: [unclosed\n").is_err());
+        assert!(parse(MetadataBlockKind::PlusesStyle, "title = \n").is_err());
+    }
+
+    #[test]
+    fn card_escapes_and_tags() {
+        let html = card_html(&json!({ "title": "<b>x</b>", "tags": ["rust", "web"], "extra": { "n": 1 } }));
+        assert!(html.contains("<dt>title</dt><dd>&lt;b&gt;x&lt;/b&gt;</dd>"));
+        assert!(html.contains(r#"<dd><span class="tag">rust</span><span class="tag">web</span></dd>"#));
+        assert!(html.contains(r#"<dd><code>{&quot;n&quot;:1}</code></dd>"#));
+    }
+}
@@ -0,0 +1,158 @@
+use ignore::Match;
+use ignore::gitignore::Gitignore;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+
+/// Per-directory ignore files, checked in this order (later files in the
+/// list lose to earlier ones within the same directory).
+const IGNORE_FILES: &[&str] = &[".ignore", ".gitignore"];
+
+/// `.gitignore`/`.ignore` rules for a served root, plus the user's global git
+/// excludes. Rules in deeper directories take precedence, negations
+/// (`!pattern`) re-include paths, and nothing below an ignored directory can
+/// be re-included, as in git. Ignore files are parsed on first use per
+/// directory and cached until [`IgnoreRules::reload`].
+#[derive(Debug)]
+pub struct IgnoreRules {
+    root: PathBuf,
+    global: Gitignore,
+    dirs: Mutex<HashMap<PathBuf, Arc<Vec<Gitignore>>>>,
+}
+
+impl IgnoreRules {
+    pub fn new(root: &Path) -> Self {
+        let (global, err) = Gitignore::global();
+        if let Some(e) = err {
+            warn!(error = %e, "Failed to read global git excludes");
+        }
+        Self { root: root.to_path_buf(), global, dirs: Mutex::new(HashMap::new()) }
+    }
+
+    /// True if `relative` (a path under the root) or any directory above it is ignored.
+    pub fn is_ignored(&self, relative: &Path, is_dir: bool) -> bool {
+        let mut prefix = PathBuf::new();
+        let mut components = relative.components().peekable();
+        while let Some(component) = components.next() {
+            prefix.push(component);
+            let last = components.peek().is_none();
+            if self.matches(&prefix, if last { is_dir } else { true }) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Forget cached ignore files, e.g. after one of them changed.
+    pub fn reload(&self) {
+        self.dirs.lock().unwrap().clear();
+    }
+
+    /// Decide a single path against the ignore files of every directory above
+    /// it, deepest first, then the global excludes.
+    fn matches(&self, relative: &Path, is_dir: bool) -> bool {
+        let absolute = self.root.join(relative);
+        for dir in relative.ancestors().skip(1) {
+            for matcher in self.matchers(dir).iter() {
+                match matcher.matched(&absolute, is_dir) {
+                    Match::None => continue,
+                    Match::Ignore(_) => return true,
+                    Match::Whitelist(_) => return false,
+                }
+            }
+        }
+        self.global.matched(relative, is_dir).is_ignore()
+    }
+
+    fn matchers(&self, dir: &Path) -> Arc<Vec<Gitignore>> {
+        let mut dirs = self.dirs.lock().unwrap();
+        let matchers = dirs.entry(dir.to_path_buf()).or_insert_with(|| {
+            let dir = self.root.join(dir);
+            let parsed = IGNORE_FILES
+                .iter()
+                .map(|name| dir.join(name))
+                .filter(|path| path.is_file())
+                .map(|path| {
+                    let (matcher, err) = Gitignore::new(&path);
+                    if let Some(e) = err {
+                        warn!(path = %path.display(), error = %e, "Failed to parse ignore file");
+                    }
+                    matcher
+                })
+                .collect();
+            Arc::new(parsed)
+        });
+        Arc::clone(matchers)
+    }
+}
+
+/// True if `path` names a file whose contents change the ignore rules.
+pub fn is_ignore_file(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| IGNORE_FILES.iter().any(|f| name == *f))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn setup_temp_dir(name: &str) -> PathBuf {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(format!("_scratch/gitignore_{name}"));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.canonicalize().unwrap()
+    }
+
+    #[test]
+    fn ignores_directories_and_their_contents() {
+        let dir = setup_temp_dir("dirs");
+        fs::write(dir.join(".gitignore"), "target/\ndist\n").unwrap();
+        let rules = IgnoreRules::new(&dir);
+        assert!(rules.is_ignored(Path::new("target"), true));
+        assert!(rules.is_ignored(Path::new("target/doc/a.md"), false));
+        assert!(rules.is_ignored(Path::new("web/dist/b.md"), false));
+        assert!(!rules.is_ignored(Path::new("src/target.md"), false));
+    }
+
+    #[test]
+    fn nested_files_and_negations() {
+        let dir = setup_temp_dir("nested");
+        fs::create_dir_all(dir.join("docs")).unwrap();
+        fs::write(dir.join(".gitignore"), "*.gen.md\n").unwrap();
+        fs::write(dir.join("docs/.gitignore"), "!keep.gen.md\nscratch.md\n").unwrap();
+        let rules = IgnoreRules::new(&dir);
+        assert!(rules.is_ignored(Path::new("a.gen.md"), false));
+        assert!(rules.is_ignored(Path::new("docs/b.gen.md"), false));
+        assert!(!rules.is_ignored(Path::new("docs/keep.gen.md"), false));
+        assert!(rules.is_ignored(Path::new("docs/scratch.md"), false));
+        assert!(!rules.is_ignored(Path::new("scratch.md"), false));
+    }
+
+    #[test]
+    fn dot_ignore_overrides_gitignore() {
+        let dir = setup_temp_dir("dot_ignore");
+        fs::write(dir.join(".gitignore"), "build/\n").unwrap();
+        fs::write(dir.join(".ignore"), "!build/\n").unwrap();
+        let rules = IgnoreRules::new(&dir);
+        assert!(!rules.is_ignored(Path::new("build/a.md"), false));
+    }
+
+    #[test]
+    fn cannot_reinclude_below_ignored_directory() {
+        let dir = setup_temp_dir("reinclude");
+        fs::write(dir.join(".gitignore"), "out/\n!out/keep.md\n").unwrap();
+        let rules = IgnoreRules::new(&dir);
+        assert!(rules.is_ignored(Path::new("out/keep.md"), false));
+    }
+
+    #[test]
+    fn reload_picks_up_changes() {
+        let dir = setup_temp_dir("reload");
+        let rules = IgnoreRules::new(&dir);
+        assert!(!rules.is_ignored(Path::new("a.md"), false));
+        fs::write(dir.join(".gitignore"), "a.md\n").unwrap();
+        rules.reload();
+        assert!(rules.is_ignored(Path::new("a.md"), false));
+    }
+}
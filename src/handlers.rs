@@ -1,37 +1,95 @@
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
     response::{
         sse::{Event, KeepAlive, Sse},
         Html, IntoResponse, Redirect,
     },
-    Json,
+    middleware,
+    routing::{get, post},
+    Json, Router,
 };
+use serde::Deserialize;
+use std::path::Component;
 use std::sync::Arc;
 use tokio_stream::wrappers::BroadcastStream;
-use tokio_stream::StreamExt;
+use tokio_stream::{StreamExt, StreamMap};
 
-use crate::state::AppState;
+use crate::assets;
+use crate::check::{Diagnostic, check_files};
+use crate::filter::should_skip;
+use crate::htpasswd::{require_basic_auth, require_root_login};
+use crate::render::escape_html;
+use crate::search::{MAX_HITS, SearchHit};
+use crate::state::{AppState, RootEvent, Roots};
+use crate::tree::{RootNode, build_tree};
+use crate::watcher::{asset_paths, rescan as rescan_files};
+
+/// The whole app: routes covering every root at the top, then each root's
+/// pages at `/` or under `/<name>`, each behind its own login if it has one.
+pub fn router(roots: Arc<Roots>) -> Router {
+    let mut app = Router::new()
+        .route("/vendor/{*name}", get(vendor_asset))
+        .route("/api/files", get(file_list))
+        .route("/api/search", get(search))
+        .route("/api/diagnostics", get(diagnostics))
+        .route("/api/rescan", post(rescan))
+        .route("/events", get(events));
+    if roots.is_mounted() {
+        app = app.route("/", get(roots_index));
+    }
+    let mut app = app
+        .route_layer(middleware::from_fn_with_state(Arc::clone(&roots), require_root_login))
+        .with_state(Arc::clone(&roots));
+    for (state, login) in roots.roots.iter().zip(&roots.logins) {
+        let base = state.base().to_string();
+        let mut pages = Router::new()
+            .route("/", get(index))
+            .route("/view/{*path}", get(view_file))
+            .route("/raw/{*path}", get(raw_file))
+            .route("/file/{*path}", get(static_file))
+            .route("/api/toc/{*path}", get(toc));
+        if let Some(login) = login {
+            pages = pages.route_layer(middleware::from_fn_with_state(Arc::clone(login), require_basic_auth));
+        }
+        let pages = pages.with_state(Arc::clone(state));
+        app = if base.is_empty() {
+            app.merge(pages)
+        } else {
+            // A nested `/` only matches `/<name>`, so send `/<name>/` there too
+            let target = base.clone();
+            let slash = get(move || async move { Redirect::permanent(&target) });
+            app.nest(&base, pages).route(&format!("{base}/"), slash)
+        };
+    }
+    app
+}
 
 /// GET / — redirect to README.md or first file or empty state
 pub async fn index(State(state): State<Arc<AppState>>) -> impl IntoResponse {
     let files = state.file_list().await;
+    let base = state.base();
     if files.contains(&"README.md".to_string()) {
-        Redirect::temporary("/view/README.md").into_response()
+        Redirect::temporary(&format!("{base}/view/README.md")).into_response()
     } else if let Some(first) = files.first() {
-        Redirect::temporary(&format!("/view/{first}")).into_response()
+        Redirect::temporary(&format!("{base}/view/{first}")).into_response()
     } else {
-        Html(state.page_shell.render_empty(&state.syntax_css_light, &state.syntax_css_dark)).into_response()
+        Html(state.page_shell.render_empty(base, &state.syntax_css_light, &state.syntax_css_dark)).into_response()
     }
 }
 
+/// GET / with several roots — redirect to the first root
+pub async fn roots_index(State(roots): State<Arc<Roots>>) -> impl IntoResponse {
+    Redirect::temporary(roots.roots[0].base())
+}
+
 /// GET /view/*path — full HTML page
 pub async fn view_file(
     Path(path): Path<String>,
     State(state): State<Arc<AppState>>,
 ) -> impl IntoResponse {
-    match state.get_rendered(&path).await {
-        Some(html) => Html(state.page_shell.render(&path, &html, &state.syntax_css_light, &state.syntax_css_dark)).into_response(),
+    match state.get_page(&path).await {
+        Some((title, html)) => Html(state.page_shell.render(state.base(), &escape_html(&title), &html, &state.syntax_css_light, &state.syntax_css_dark)).into_response(),
         None => (StatusCode::NOT_FOUND, Html("File not found".to_string())).into_response(),
     }
 }
@@ -47,22 +105,189 @@ pub async fn raw_file(
     }
 }
 
-/// GET /api/files — JSON list of file paths
-pub async fn file_list(State(state): State<Arc<AppState>>) -> Json<Vec<String>> {
-    Json(state.file_list().await)
+/// GET /file/*path — static asset (images, PDFs, ...) from the served root.
+/// In single-file mode the root is the file's directory, so only the files
+/// the document links to are served.
+pub async fn static_file(
+    Path(path): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    let not_found = || (StatusCode::NOT_FOUND, "File not found").into_response();
+
+    let relative = std::path::Path::new(&path);
+    let is_plain = relative.components().all(|c| matches!(c, Component::Normal(_)));
+    if !is_plain || should_skip(relative) {
+        return not_found();
+    }
+    if let Some(file) = state.filter.single_file()
+        && !asset_paths(&state, &file.to_string_lossy()).await.contains(&path)
+    {
+        return not_found();
+    }
+
+    // Canonicalize to resolve symlinks, then make sure we're still under root.
+    let Ok(full) = tokio::fs::canonicalize(state.root.join(relative)).await else {
+        return not_found();
+    };
+    if !full.starts_with(&state.root) {
+        return not_found();
+    }
+
+    match tokio::fs::read(&full).await {
+        Ok(bytes) => {
+            let mime = mime_guess::from_path(&full).first_or_octet_stream();
+            ([(header::CONTENT_TYPE, mime.to_string())], bytes).into_response()
+        }
+        Err(_) => not_found(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct FileListParams {
+    #[serde(default)]
+    tree: bool,
 }
 
-/// GET /events — SSE stream
-pub async fn events(
+/// GET /vendor/*name — bundled third-party scripts, styles and fonts
+pub async fn vendor_asset(Path(name): Path<String>) -> impl IntoResponse {
+    match assets::VENDOR_FILES.iter().find(|(path, _, _)| *path == name) {
+        Some((_, content_type, body)) => (
+            [
+                (header::CONTENT_TYPE, *content_type),
+                (header::CACHE_CONTROL, "public, max-age=86400"),
+            ],
+            *body,
+        )
+            .into_response(),
+        None => (StatusCode::NOT_FOUND, "Not found").into_response(),
+    }
+}
+
+/// GET /api/files — JSON list of file paths, or a nested tree with `?tree=true`.
+/// With several roots, paths are prefixed with the root name and the tree
+/// has one `root` node per root the request may see.
+pub async fn file_list(
+    Query(params): Query<FileListParams>,
+    State(roots): State<Arc<Roots>>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    if !roots.is_mounted() {
+        let files = roots.roots[0].file_list().await;
+        return if params.tree { Json(build_tree(&files)).into_response() } else { Json(files).into_response() };
+    }
+    let mut tree = Vec::new();
+    let mut list = Vec::new();
+    for root in roots.visible(&headers).await {
+        let files = root.file_list().await;
+        if params.tree {
+            tree.push(RootNode::new(root.name(), &files));
+        } else {
+            list.extend(files.iter().map(|path| format!("{}/{path}", root.name())));
+        }
+    }
+    if params.tree { Json(tree).into_response() } else { Json(list).into_response() }
+}
+
+/// GET /api/toc/*path — JSON heading outline for a file
+pub async fn toc(
+    Path(path): Path<String>,
     State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    match state.get_toc(&path).await {
+        Some(toc) => Json(toc).into_response(),
+        None => (StatusCode::NOT_FOUND, "File not found".to_string()).into_response(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SearchParams {
+    #[serde(default)]
+    q: String,
+}
+
+/// GET /api/search?q= — ranked full-text search hits across all roots
+pub async fn search(
+    Query(params): Query<SearchParams>,
+    State(roots): State<Arc<Roots>>,
+    headers: HeaderMap,
+) -> Json<Vec<SearchHit>> {
+    let mut hits = Vec::new();
+    for root in roots.visible(&headers).await {
+        hits.extend(root.search(&params.q).await);
+    }
+    hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+    hits.truncate(MAX_HITS);
+    Json(hits)
+}
+
+/// GET /api/diagnostics — broken links, anchors, images and footnotes in all files
+pub async fn diagnostics(State(roots): State<Arc<Roots>>, headers: HeaderMap) -> Json<Vec<Diagnostic>> {
+    let mut diagnostics = Vec::new();
+    for root in roots.visible(&headers).await {
+        let files = root.files.read().await;
+        diagnostics.extend(check_files(&root.root, &files).into_iter().map(|mut d| {
+            if roots.is_mounted() {
+                d.path = format!("{}/{}", root.name(), d.path);
+            }
+            d
+        }));
+    }
+    Json(diagnostics)
+}
+
+/// POST /api/rescan — rediscover all files and return what changed
+pub async fn rescan(State(roots): State<Arc<Roots>>, headers: HeaderMap) -> Json<Vec<RootEvent>> {
+    let mut changes = Vec::new();
+    for root in roots.visible(&headers).await {
+        let name = root.name().to_string();
+        changes.extend(rescan_files(root).await.into_iter().map(|event| RootEvent { root: name.clone(), event }));
+    }
+    Json(changes)
+}
+
+/// GET /events — SSE stream of changes in every root, tagged with the root name
+pub async fn events(
+    State(roots): State<Arc<Roots>>,
+    headers: HeaderMap,
 ) -> Sse<impl tokio_stream::Stream<Item = Result<Event, std::convert::Infallible>>> {
-    let rx = state.tx.subscribe();
-    let stream = BroadcastStream::new(rx).filter_map(|result| match result {
+    let mut streams = StreamMap::new();
+    for root in roots.visible(&headers).await {
+        streams.insert(root.name().to_string(), BroadcastStream::new(root.tx.subscribe()));
+    }
+    let stream = streams.filter_map(|(root, result)| match result {
         Ok(event) => {
-            let json = serde_json::to_string(&event).ok()?;
+            let json = serde_json::to_string(&RootEvent { root, event }).ok()?;
             Some(Ok(Event::default().data(json)))
         }
         Err(_) => None,
     });
     Sse::new(stream).keep_alive(KeepAlive::default())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::MarkdownStyle;
+    use crate::filter::FileFilter;
+    use crate::render::{RenderOptions, render_markdown};
+    use std::path::PathBuf;
+
+    #[tokio::test]
+    async fn single_file_serves_only_linked_files() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("_scratch/handlers_single");
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("img")).unwrap();
+        std::fs::write(root.join("img/a b.png"), b"png").unwrap();
+        std::fs::write(root.join("secret.txt"), "secret").unwrap();
+        let root = root.canonicalize().unwrap();
+        let filter = FileFilter::default().with_single_file(std::path::Path::new("NOTES.md"));
+        let options = RenderOptions::default();
+        let state = AppState::new(root, "InspiredGitHub", "Monokai", options, filter, MarkdownStyle::default());
+        let doc = render_markdown("![a](img/a%20b.png)", "NOTES.md", &state.render_options);
+        state.upsert("NOTES.md".to_string(), doc).await;
+
+        let get = |path: &str| static_file(Path(path.to_string()), State(Arc::clone(&state)));
+        assert_eq!(get("img/a b.png").await.into_response().status(), StatusCode::OK);
+        assert_eq!(get("secret.txt").await.into_response().status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,268 @@
+use argon2::{Argon2, PasswordHash, PasswordVerifier};
+use axum::extract::{Request, State};
+use axum::http::{HeaderMap, HeaderValue, StatusCode, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use std::collections::HashSet;
+use std::hash::{BuildHasher, RandomState};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use subtle::ConstantTimeEq;
+
+use crate::state::Roots;
+
+/// Failed credentials remembered at most, so repeated requests with the same
+/// wrong password do not each cost a hash.
+const MAX_REJECTED: usize = 1024;
+
+/// Users and password hashes from an htpasswd file. Only bcrypt (`$2y$`,
+/// `$2a$`, `$2b$`) and argon2 (`$argon2id$` etc.) hashes are accepted.
+///
+/// Hashes are slow on purpose, so credentials that verified once are
+/// remembered (as a keyed hash, never in plain text) for later requests, as
+/// are a bounded number of failed ones.
+#[derive(Debug)]
+pub struct Htpasswd {
+    users: Vec<(String, String)>,
+    verified: Mutex<HashSet<u64>>,
+    rejected: Mutex<HashSet<u64>>,
+    hasher: RandomState,
+}
+
+impl Htpasswd {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        Self::parse(&text).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    /// Parse `user:hash` lines, skipping blank lines and `#` comments.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut users = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((user, hash)) = line.split_once(':') else {
+                return Err(format!("line {}: expected `user:hash`", i + 1));
+            };
+            if !is_bcrypt(hash) && !hash.starts_with("$argon2") {
+                return Err(format!("line {}: unsupported hash for {user}; use bcrypt (htpasswd -B) or argon2", i + 1));
+            }
+            users.push((user.to_string(), hash.to_string()));
+        }
+        if users.is_empty() {
+            return Err("no users".to_string());
+        }
+        Ok(Self {
+            users,
+            verified: Mutex::new(HashSet::new()),
+            rejected: Mutex::new(HashSet::new()),
+            hasher: RandomState::new(),
+        })
+    }
+
+    /// True if `user` exists and `password` matches its hash. Every user name
+    /// is compared in constant time, and unknown users still cost one hash
+    /// check, so timing does not reveal which names exist.
+    pub fn verify(&self, user: &str, password: &str) -> bool {
+        let key = self.hasher.hash_one((user, password));
+        if self.verified.lock().unwrap().contains(&key) {
+            return true;
+        }
+        if self.rejected.lock().unwrap().contains(&key) {
+            return false;
+        }
+
+        let mut found = None;
+        for (name, hash) in &self.users {
+            if bool::from(name.as_bytes().ct_eq(user.as_bytes())) && found.is_none() {
+                found = Some(hash);
+            }
+        }
+        let hash = found.unwrap_or(&self.users[0].1);
+        let matches = if is_bcrypt(hash) {
+            bcrypt::verify(password, hash).unwrap_or(false)
+        } else {
+            PasswordHash::new(hash).is_ok_and(|parsed| Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok())
+        };
+        if matches && found.is_some() {
+            self.verified.lock().unwrap().insert(key);
+            return true;
+        }
+        let mut rejected = self.rejected.lock().unwrap();
+        if rejected.len() >= MAX_REJECTED {
+            rejected.clear();
+        }
+        rejected.insert(key);
+        false
+    }
+
+    /// True if the request carries Basic credentials for a user in this file.
+    pub async fn allows(self: &Arc<Self>, headers: &HeaderMap) -> bool {
+        let credentials = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Basic "))
+            .and_then(|encoded| STANDARD.decode(encoded.trim()).ok())
+            .and_then(|decoded| String::from_utf8(decoded).ok());
+        let Some((user, password)) = credentials.as_deref().and_then(|c| c.split_once(':')) else {
+            return false;
+        };
+        let (htpasswd, user, password) = (Arc::clone(self), user.to_string(), password.to_string());
+        tokio::task::spawn_blocking(move || htpasswd.verify(&user, &password)).await.unwrap_or(false)
+    }
+}
+
+fn is_bcrypt(hash: &str) -> bool {
+    ["$2y$", "$2a$", "$2b$"].iter().any(|prefix| hash.starts_with(prefix))
+}
+
+/// Middleware that requires HTTP Basic credentials listed in the htpasswd file.
+pub async fn require_basic_auth(State(htpasswd): State<Arc<Htpasswd>>, request: Request, next: Next) -> Response {
+    if htpasswd.allows(request.headers()).await {
+        return next.run(request).await;
+    }
+    challenge()
+}
+
+/// Middleware for routes that cover every root, such as search and `/events`:
+/// the request must be allowed into at least one root. Handlers then only
+/// show the roots it may see (see [`Roots::visible`]).
+pub async fn require_root_login(State(roots): State<Arc<Roots>>, request: Request, next: Next) -> Response {
+    if roots.visible(request.headers()).await.is_empty() {
+        return challenge();
+    }
+    next.run(request).await
+}
+
+/// 401 asking the browser for a login. Every root uses the same realm, so
+/// the browser sends the same credentials everywhere on the server.
+fn challenge() -> Response {
+    let mut response = (StatusCode::UNAUTHORIZED, "Authentication required").into_response();
+    response
+        .headers_mut()
+        .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Basic realm=\"marpe\", charset=\"UTF-8\""));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use argon2::PasswordHasher;
+    use argon2::password_hash::SaltString;
+    use axum::Router;
+    use axum::body::Body;
+    use axum::routing::get;
+    use crate::assets::MarkdownStyle;
+    use crate::filter::FileFilter;
+    use crate::render::{RenderOptions, Rendered};
+    use crate::state::AppState;
+    use std::path::PathBuf;
+    use tower::ServiceExt;
+
+    fn htpasswd() -> Htpasswd {
+        let bcrypt_hash = bcrypt::hash("bpass", 4).unwrap();
+        Htpasswd::parse(&format!("# team\nbob:{bcrypt_hash}\n\nann:{}\n", argon_hash("apass"))).unwrap()
+    }
+
+    fn argon_hash(password: &str) -> String {
+        let salt = SaltString::from_b64("c29tZXNhbHRzb21lc2FsdA").unwrap();
+        let params = argon2::Params::new(1024, 1, 1, None).unwrap();
+        let argon = Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params);
+        argon.hash_password(password.as_bytes(), &salt).unwrap().to_string()
+    }
+
+    fn app() -> Router {
+        Router::new()
+            .route("/", get(|| async { "index" }))
+            .route("/events", get(|| async { "events" }))
+            .layer(axum::middleware::from_fn_with_state(Arc::new(htpasswd()), require_basic_auth))
+    }
+
+    fn request(uri: &str, credentials: Option<&str>) -> Request {
+        let mut request = Request::builder().uri(uri);
+        if let Some(credentials) = credentials {
+            request = request.header(header::AUTHORIZATION, format!("Basic {}", STANDARD.encode(credentials)));
+        }
+        request.body(Body::empty()).unwrap()
+    }
+
+    async fn status(uri: &str, credentials: Option<&str>) -> StatusCode {
+        app().oneshot(request(uri, credentials)).await.unwrap().status()
+    }
+
+    #[test]
+    fn verifies_bcrypt_and_argon2() {
+        let htpasswd = htpasswd();
+        assert!(htpasswd.verify("bob", "bpass"));
+        assert!(htpasswd.verify("bob", "bpass"));
+        assert!(htpasswd.verify("ann", "apass"));
+        assert!(!htpasswd.verify("bob", "apass"));
+        assert!(!htpasswd.verify("ann", "bpass"));
+        assert!(!htpasswd.verify("eve", "bpass"));
+    }
+
+    #[test]
+    fn rejects_unsupported_files() {
+        assert!(Htpasswd::parse("").is_err());
+        assert!(Htpasswd::parse("bob").is_err());
+        let err = Htpasswd::parse("bob:$apr1$abc$def\n").unwrap_err();
+        assert!(err.contains("line 1"), "{err}");
+        assert!(Htpasswd::parse("bob:plaintext\n").is_err());
+    }
+
+    #[tokio::test]
+    async fn requires_valid_credentials() {
+        assert_eq!(status("/", None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status("/", Some("bob:wrong")).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status("/", Some("nobody")).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status("/", Some("bob:bpass")).await, StatusCode::OK);
+        assert_eq!(status("/events", Some("ann:apass")).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn challenges_browsers() {
+        let request = Request::builder().uri("/").body(Body::empty()).unwrap();
+        let response = app().oneshot(request).await.unwrap();
+        assert_eq!(response.headers()[header::WWW_AUTHENTICATE], "Basic realm=\"marpe\", charset=\"UTF-8\"");
+    }
+
+    #[tokio::test]
+    async fn each_root_has_its_own_users() {
+        let mut states = Vec::new();
+        for name in ["a", "b"] {
+            let options = RenderOptions { base: format!("/{name}"), ..Default::default() };
+            let state = AppState::new(
+                PathBuf::from("."),
+                "InspiredGitHub",
+                "Monokai",
+                options,
+                FileFilter::default(),
+                MarkdownStyle::default(),
+            );
+            state.upsert(format!("{name}.md"), Rendered::default()).await;
+            states.push(state);
+        }
+        let ann_only = Htpasswd::parse(&format!("ann:{}\n", argon_hash("apass"))).unwrap();
+        let roots = Roots::new(states, vec![Some(Arc::new(htpasswd())), Some(Arc::new(ann_only))]);
+        let send = |uri: &str, credentials: Option<&str>| {
+            crate::handlers::router(Arc::clone(&roots)).oneshot(request(uri, credentials))
+        };
+
+        assert_eq!(send("/a/view/a.md", Some("bob:bpass")).await.unwrap().status(), StatusCode::OK);
+        assert_eq!(send("/b/view/b.md", Some("bob:bpass")).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(send("/b/view/b.md", Some("ann:apass")).await.unwrap().status(), StatusCode::OK);
+        assert_eq!(send("/api/files", None).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+
+        // Routes covering every root only show the roots the user may see.
+        let response = send("/api/files", Some("bob:bpass")).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, r#"["a/a.md"]"#);
+        let response = send("/api/files", Some("ann:apass")).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, r#"["a/a.md","b/b.md"]"#);
+    }
+}
@@ -1,18 +1,30 @@
 mod assets;
+mod auth;
+mod bind;
+mod check;
 mod cli;
+mod config;
 mod discovery;
+mod export;
+mod filter;
+mod frontmatter;
+mod gitignore;
 mod handlers;
+mod htpasswd;
 mod render;
+mod search;
 mod state;
 mod tls;
+mod tree;
 mod watcher;
 
-use axum::Router;
-use axum::routing::get;
+use percent_encoding::{NON_ALPHANUMERIC, utf8_percent_encode};
+use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
 use tower_http::trace::TraceLayer;
-use tracing::info;
+use tracing::{info, warn};
 
 const SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(2);
 
@@ -21,40 +33,175 @@ async fn main() {
     tracing_subscriber::fmt::init();
 
     let args = cli::parse_args();
-    let root = args.root.canonicalize().expect("Invalid directory path");
-    info!(path = %root.display(), "Serving markdown files from");
 
-    let state = state::AppState::new(root.clone(), &args.syntax_theme_light, &args.syntax_theme_dark);
+    match &args.command {
+        cli::Command::Themes => {
+            for name in state::theme_names() {
+                println!("{name}");
+            }
+            return;
+        }
+        cli::Command::Render { input, out, standalone } => {
+            render_file(&args, input.as_deref(), out.as_deref(), *standalone);
+            return;
+        }
+        cli::Command::Serve | cli::Command::Export { .. } | cli::Command::Check => {}
+    }
 
-    // Initial file discovery
-    let files = discovery::discover_and_render(&root);
-    let count = files.len();
-    {
-        let mut map = state.files.write().await;
-        *map = files;
+    // With several roots, or a named one, each is mounted under `/<name>`.
+    let mounted = args.roots.len() > 1 || args.roots.iter().any(|spec| spec.name.is_some());
+    let mut roots: Vec<(String, std::path::PathBuf)> = Vec::new();
+    // A single file is previewed on its own, from its directory.
+    let mut single_file = None;
+    for spec in &args.roots {
+        let mut path = spec.path.canonicalize().unwrap_or_else(|e| {
+            eprintln!("Invalid directory {}: {e}", spec.path.display());
+            std::process::exit(1);
+        });
+        if path.is_file() {
+            if mounted {
+                eprintln!("{} is a file; a single file can only be previewed on its own", spec.path.display());
+                std::process::exit(1);
+            }
+            single_file = path.file_name().map(std::path::PathBuf::from);
+            path.pop();
+        }
+        let name = match (&spec.name, mounted) {
+            (Some(name), _) => name.clone(),
+            (None, true) => cli::default_root_name(&path),
+            (None, false) => String::new(),
+        };
+        if mounted && let Err(e) = cli::check_root_name(&name) {
+            eprintln!("{e}; name {} with NAME=PATH", path.display());
+            std::process::exit(1);
+        }
+        if roots.iter().any(|(other, _)| *other == name) {
+            eprintln!("Two directories are named '{name}'; name them with NAME=PATH");
+            std::process::exit(1);
+        }
+        roots.push((name, path));
+    }
+    let filter = filter::FileFilter::new(&args.include, &args.exclude).unwrap_or_else(|e| {
+        eprintln!("Invalid glob pattern: {e}");
+        std::process::exit(1);
+    });
+    let filter_for = |root: &std::path::Path| match &single_file {
+        Some(file) => filter.clone().with_single_file(file),
+        None if args.no_ignore => filter.clone(),
+        None => filter.clone().with_ignore_files(root),
+    };
+
+    if let cli::Command::Export { out } = &args.command {
+        // Several roots each get a site in `out/<name>/`, under a page linking them.
+        for (name, root) in &roots {
+            let filter = filter_for(root);
+            let options = export::ExportOptions {
+                filter: &filter,
+                sanitize: args.sanitize,
+                syntax_theme_light: &args.syntax_theme_light,
+                syntax_theme_dark: &args.syntax_theme_dark,
+                style: args.style,
+            };
+            let dir = if mounted { out.join(name) } else { out.clone() };
+            if let Err(e) = export::export_site(root, &dir, &options) {
+                eprintln!("Export to {} failed: {e}", dir.display());
+                std::process::exit(1);
+            }
+        }
+        if mounted {
+            let names: Vec<String> = roots.iter().map(|(name, _)| name.clone()).collect();
+            let index = export::export_roots_index(out, &names, &args.syntax_theme_light, &args.syntax_theme_dark, args.style);
+            if let Err(e) = index {
+                eprintln!("Export to {} failed: {e}", out.display());
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if let cli::Command::Check = args.command {
+        let render_options = render::RenderOptions { sanitize: args.sanitize, ..Default::default() };
+        let (mut checked, mut problems) = (0, 0);
+        for (name, root) in &roots {
+            let files = discovery::discover_and_render(root, &render_options, &filter_for(root));
+            for mut diagnostic in check::check_files(root, &files) {
+                if mounted {
+                    diagnostic.path = format!("{name}/{}", diagnostic.path);
+                }
+                println!("{diagnostic}");
+                problems += 1;
+            }
+            checked += files.len();
+        }
+        eprintln!("Checked {checked} files, {problems} problems");
+        if problems > 0 {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let mut states = Vec::new();
+    let mut watchers = Vec::new();
+    for (name, root) in &roots {
+        match &single_file {
+            Some(file) => info!(path = %root.join(file).display(), "Previewing single file"),
+            None if mounted => info!(path = %root.display(), name = %name, "Serving markdown files from"),
+            None => info!(path = %root.display(), "Serving markdown files from"),
+        }
+
+        let base = if mounted { format!("/{name}") } else { String::new() };
+        let render_options = render::RenderOptions { sanitize: args.sanitize, base, ..Default::default() };
+        let filter = filter_for(root);
+        let files = discovery::discover_and_render(root, &render_options, &filter);
+        let state = state::AppState::new(
+            root.clone(),
+            &args.syntax_theme_light,
+            &args.syntax_theme_dark,
+            render_options,
+            filter,
+            args.style,
+        );
+
+        // Initial file discovery
+        let count = files.len();
+        state.load(files).await;
+        info!(count, "Discovered markdown files");
+
+        // Start file watcher
+        let watcher = watcher::start_watcher(Arc::clone(&state), Duration::from_millis(args.debounce))
+            .expect("Failed to start file watcher");
+        watchers.push(watcher);
+        states.push(state);
     }
-    info!(count, "Discovered markdown files");
-
-    // Start file watcher
-    let _watcher = watcher::start_watcher(Arc::clone(&state))
-        .expect("Failed to start file watcher");
-
-    // Build router
-    let app = Router::new()
-        .route("/", get(handlers::index))
-        .route("/view/{*path}", get(handlers::view_file))
-        .route("/raw/{*path}", get(handlers::raw_file))
-        .route("/api/files", get(handlers::file_list))
-        .route("/events", get(handlers::events))
-        .layer(TraceLayer::new_for_http())
-        .with_state(state);
+
+    // Roots sharing an htpasswd file share one user list, and its cache.
+    let mut loaded: HashMap<&std::path::Path, Arc<htpasswd::Htpasswd>> = HashMap::new();
+    let mut logins = Vec::new();
+    for path in &args.htpasswd {
+        logins.push(path.as_deref().map(|path| {
+            let login = loaded.entry(path).or_insert_with(|| {
+                Arc::new(htpasswd::Htpasswd::load(path).unwrap_or_else(|e| {
+                    eprintln!("Invalid htpasswd file {e}");
+                    std::process::exit(1);
+                }))
+            });
+            Arc::clone(login)
+        }));
+    }
+    let app = handlers::router(state::Roots::new(states, logins));
+
+    let app = match &args.token {
+        Some(token) => app.layer(axum::middleware::from_fn_with_state(Arc::<str>::from(token.as_str()), auth::require_token)),
+        None => app,
+    };
+    let app = app.layer(TraceLayer::new_for_http());
 
     let mut listener = None;
     let mut port = args.port;
     let end_port = args.port.saturating_add(9);
     for p in args.port..=end_port {
-        let addr = format!("0.0.0.0:{}", p);
-        match tokio::net::TcpListener::bind(&addr).await {
+        let addr = SocketAddr::new(args.bind.addr(), p);
+        match tokio::net::TcpListener::bind(addr).await {
             Ok(l) => {
                 listener = Some(l);
                 port = p;
@@ -64,14 +211,23 @@ async fn main() {
                 info!(port = p, "Port already in use, trying next one");
                 continue;
             }
-            Err(e) => panic!("Failed to bind to port {}: {}", p, e),
+            Err(e) => {
+                eprintln!("Failed to bind {addr}: {e}");
+                std::process::exit(1);
+            }
         }
     }
 
     let listener = listener.expect("Could not find a free port in range");
     let protocol = if args.tls { "https" } else { "http" };
-    let url = format!("{}://localhost:{}", protocol, port);
-    info!(port, "Server listening on {}", url);
+    let mut url = format!("{}://{}:{}", protocol, args.bind.url_host(), port);
+    if let Some(token) = &args.token {
+        url.push_str(&format!("/?{}={}", auth::TOKEN_PARAM, utf8_percent_encode(token, NON_ALPHANUMERIC)));
+    }
+    info!(addr = %SocketAddr::new(args.bind.addr(), port), "Server listening on {}", url);
+    if !args.bind.is_loopback() {
+        warn!(bind = %args.bind, "Listening on a non-loopback address; other machines on the network can read these files");
+    }
 
     if args.open
         && let Err(e) = open::that(&url)
@@ -112,6 +268,39 @@ async fn main() {
     }
 }
 
+/// `marpe render`: render one file (or stdin) to a fragment or a full page.
+fn render_file(args: &cli::Args, input: Option<&std::path::Path>, out: Option<&std::path::Path>, standalone: bool) {
+    let (name, content) = match input {
+        Some(path) => (path.to_string_lossy().to_string(), std::fs::read_to_string(path)),
+        None => ("stdin.md".to_string(), std::io::read_to_string(std::io::stdin())),
+    };
+    let content = content.unwrap_or_else(|e| {
+        eprintln!("Failed to read {name}: {e}");
+        std::process::exit(1);
+    });
+
+    let options =
+        render::RenderOptions { sanitize: args.sanitize, links: render::LinkStyle::Relative, ..Default::default() };
+    let doc = render::render_markdown(&content, &name, &options);
+    let html = if standalone {
+        export::standalone_page(&doc, &name, &args.syntax_theme_light, &args.syntax_theme_dark, args.style)
+    } else {
+        doc.html
+    };
+
+    let written = match out {
+        // A standalone page loads its math fonts and mermaid from `_assets/` beside it
+        Some(path) if standalone => std::fs::write(path, html)
+            .and_then(|()| export::write_assets(path.parent().unwrap_or(std::path::Path::new("")))),
+        Some(path) => std::fs::write(path, html),
+        None => std::io::Write::write_all(&mut std::io::stdout(), html.as_bytes()),
+    };
+    if let Err(e) = written {
+        eprintln!("Failed to write output: {e}");
+        std::process::exit(1);
+    }
+}
+
 async fn shutdown_signal() {
     tokio::signal::ctrl_c()
         .await
@@ -1,24 +1,238 @@
-use pulldown_cmark::{CodeBlockKind, CowStr, Event, Options, Parser, Tag, TagEnd, html};
+use clap::ValueEnum;
+use percent_encoding::{AsciiSet, CONTROLS, utf8_percent_encode};
+use pulldown_cmark::{
+    BrokenLink, CodeBlockKind, CowStr, Event, LinkType, MetadataBlockKind, Options, Parser, Tag, TagEnd, html,
+};
+use pulldown_latex::config::DisplayMode;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
 use std::sync::LazyLock;
 use syntect::html::{ClassedHTMLGenerator, ClassStyle};
 use syntect::parsing::SyntaxSet;
 use syntect::util::LinesWithEndings;
 
+use crate::filter::has_markdown_extension;
+use crate::frontmatter;
+
 static SYNTAX_SET: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
 
-/// Render markdown text to an HTML fragment string with syntax highlighting.
-pub fn render_markdown(input: &str) -> String {
-    let options = Options::ENABLE_TABLES
+/// MathML Core elements emitted by the math renderer.
+const MATHML_TAGS: &[&str] = &[
+    "math", "semantics", "annotation", "merror", "mfrac", "mi", "mmultiscripts", "mn", "mo",
+    "mover", "mpadded", "mphantom", "mprescripts", "mroot", "mrow", "ms", "mspace", "msqrt",
+    "mstyle", "msub", "msubsup", "msup", "mtable", "mtd", "mtext", "mtr", "munder", "munderover",
+    "none",
+];
+
+/// Presentation attributes used on MathML elements.
+const MATHML_ATTRIBUTES: &[&str] = &[
+    "display", "displaystyle", "scriptlevel", "mathvariant", "stretchy", "symmetric", "largeop",
+    "movablelimits", "minsize", "maxsize", "lspace", "rspace", "linethickness", "width", "height",
+    "depth", "encoding", "columnspan", "rowspan", "style",
+];
+
+/// Prefix the sanitizer puts on every id in a document.
+pub const USER_CONTENT_PREFIX: &str = "user-content-";
+
+/// GitHub-compatible HTML allowlist, extended with the classes and attributes
+/// our own output relies on (syntax highlighting spans, task list checkboxes,
+/// footnote ids, table alignment, MathML).
+static SANITIZER: LazyLock<ammonia::Builder<'static>> = LazyLock::new(|| {
+    let mut builder = ammonia::Builder::default();
+    builder
+        .add_generic_attributes(["class", "id"])
+        // As on GitHub, so a document can't clobber the page's own ids or
+        // globals; links keep the bare name and `app.js` resolves it.
+        .id_prefix(Some(USER_CONTENT_PREFIX))
+        .add_tags(["input"])
+        .add_tag_attributes("a", ["aria-hidden"])
+        .add_tag_attributes("input", ["type", "checked", "disabled"])
+        .add_tag_attributes("th", ["style"])
+        .add_tag_attributes("td", ["style"])
+        .add_tags(MATHML_TAGS.iter().copied());
+    for tag in MATHML_TAGS {
+        builder.add_tag_attributes(tag, MATHML_ATTRIBUTES.iter().copied());
+    }
+    // The properties math rendering uses; table cells only get `text-align`.
+    builder.filter_style_properties(HashSet::from([
+        "text-align",
+        "color",
+        "border",
+        "border-color",
+        "margin-left",
+        "height",
+    ]));
+    builder.attribute_filter(|element, attribute, value| match (element, attribute) {
+        ("th" | "td", "style") => Some(text_align_only(value).into()),
+        _ => Some(value.into()),
+    });
+    builder
+});
+
+/// The `text-align: <keyword>` declarations of a style attribute, the only
+/// styling tables need.
+fn text_align_only(style: &str) -> String {
+    style
+        .split(';')
+        .filter(|decl| {
+            decl.split_once(':').is_some_and(|(property, value)| {
+                property.trim().eq_ignore_ascii_case("text-align")
+                    && value.trim().chars().all(|c| c.is_ascii_alphabetic() || c == '-')
+            })
+        })
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+/// How raw HTML embedded in markdown is treated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SanitizeMode {
+    /// Strip everything outside a GitHub-compatible allowlist.
+    #[default]
+    Strict,
+    /// Pass raw HTML through untouched. Only for trusted content.
+    Off,
+}
+
+/// How relative links and image URLs are emitted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LinkStyle {
+    /// Root-absolute `/view/` and `/file/` URLs for the live server.
+    #[default]
+    Server,
+    /// Relative URLs with `.md` links pointing at `.html` pages, for static export.
+    Relative,
+}
+
+/// Options that affect how markdown is rendered to HTML.
+#[derive(Debug, Clone, Default)]
+pub struct RenderOptions {
+    pub sanitize: SanitizeMode,
+    pub links: LinkStyle,
+    /// URL prefix the root is mounted under when serving several roots, e.g.
+    /// `/docs`; empty otherwise. Prepended to `/view/` and `/file/` links.
+    pub base: String,
+}
+
+/// URL prefix under which rendered markdown documents are viewed.
+pub const VIEW_PREFIX: &str = "/view/";
+
+/// URL prefix under which non-markdown files in the served root are exposed.
+pub const FILE_PREFIX: &str = "/file/";
+
+/// Characters escaped when turning a filesystem path segment into a URL segment.
+pub const PATH_SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ').add(b'"').add(b'#').add(b'%').add(b'<').add(b'>')
+    .add(b'?').add(b'`').add(b'{').add(b'}');
+
+/// One entry in a document's heading outline.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TocEntry {
+    pub level: u8,
+    pub text: String,
+    pub slug: String,
+}
+
+/// The result of rendering a markdown document.
+#[derive(Debug, Clone, Default)]
+pub struct Rendered {
+    pub html: String,
+    pub toc: Vec<TocEntry>,
+    pub sections: Vec<Section>,
+    /// Root-relative (URL-encoded) paths of non-markdown files the document
+    /// references through relative links or images.
+    pub assets: Vec<String>,
+    /// Parsed YAML or TOML front matter, if the document has a valid block.
+    pub front_matter: Option<Value>,
+    /// Links, images and unresolved references in the source, for `check`.
+    pub references: Vec<Reference>,
+}
+
+impl Rendered {
+    /// The front matter `title`, if it is a string.
+    pub fn title(&self) -> Option<&str> {
+        self.front_matter.as_ref()?.get("title")?.as_str()
+    }
+}
+
+/// A reference found in the markdown source, with its 1-based line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    pub line: usize,
+    pub kind: ReferenceKind,
+    /// The URL as written for links and images, or the label otherwise.
+    pub target: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReferenceKind {
+    Link,
+    Image,
+    /// `[^label]` with no matching footnote definition.
+    UndefinedFootnote,
+    /// `[text][label]` with no matching link reference definition.
+    UndefinedLabel,
+}
+
+/// Plain text of a document split at headings, used for search.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Section {
+    /// The heading this section starts with; `None` for text before the first heading.
+    pub heading: Option<TocEntry>,
+    pub text: String,
+}
+
+/// Render markdown text to an HTML fragment with syntax highlighting, along
+/// with its heading outline and plain-text sections. `doc_path` is the
+/// document's path relative to the served root; relative image and link URLs
+/// are resolved against its directory.
+pub fn render_markdown(input: &str, doc_path: &str, options: &RenderOptions) -> Rendered {
+    let parser_options = Options::ENABLE_TABLES
         | Options::ENABLE_STRIKETHROUGH
         | Options::ENABLE_TASKLISTS
-        | Options::ENABLE_FOOTNOTES;
-    let parser = Parser::new_ext(input, options);
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_MATH
+        | Options::ENABLE_YAML_STYLE_METADATA_BLOCKS
+        | Options::ENABLE_PLUSES_DELIMITED_METADATA_BLOCKS;
+    let mut undefined_labels: Vec<(Range<usize>, String)> = Vec::new();
+    let parser = Parser::new_with_broken_link_callback(
+        input,
+        parser_options,
+        Some(|link: BrokenLink| {
+            if matches!(link.link_type, LinkType::Reference | LinkType::Collapsed) {
+                undefined_labels.push((link.span, link.reference.to_string()));
+            }
+            None
+        }),
+    );
+    let parsed: Vec<(Event, Range<usize>)> = parser.into_offset_iter().collect();
+    let references = collect_references(input, &parsed, undefined_labels);
 
     let mut output = String::new();
     let mut code_buf: Option<(String, String)> = None; // (language, accumulated text)
 
-    let events: Vec<Event> = parser.collect();
+    let (events, metadata) = take_metadata(parsed.into_iter().map(|(event, _)| event).collect());
+    let front_matter = match metadata {
+        Some((kind, text)) => match frontmatter::parse(kind, &text) {
+            Ok(Value::Null) => None,
+            Ok(value) => {
+                output.push_str(&frontmatter::card_html(&value));
+                Some(value)
+            }
+            Err(e) => {
+                output.push_str(&frontmatter::error_html(&e));
+                None
+            }
+        },
+        None => None,
+    };
+    let (events, toc) = anchor_headings(events);
+    let sections = collect_sections(&events, &toc);
     let mut highlighted_events: Vec<Event> = Vec::new();
+    let mut links = LinkRewriter { doc_path, style: options.links, base: &options.base, assets: Vec::new() };
 
     for event in events {
         match &event {
@@ -33,17 +247,330 @@ pub fn render_markdown(input: &str) -> String {
             }
             Event::End(TagEnd::CodeBlock) if code_buf.is_some() => {
                 let (lang, code) = code_buf.take().unwrap();
-                let highlighted = try_highlight(&lang, &code);
-                highlighted_events.push(Event::Html(CowStr::from(highlighted)));
+                let block = if lang == "mermaid" {
+                    mermaid_block(&code)
+                } else {
+                    try_highlight(&lang, &code)
+                };
+                highlighted_events.push(Event::Html(CowStr::from(block)));
+                continue;
+            }
+            Event::InlineMath(tex) => {
+                highlighted_events.push(Event::InlineHtml(render_math(tex, DisplayMode::Inline).into()));
+                continue;
+            }
+            Event::DisplayMath(tex) => {
+                highlighted_events.push(Event::InlineHtml(render_math(tex, DisplayMode::Block).into()));
                 continue;
             }
             _ => {}
         }
-        highlighted_events.push(event);
+        highlighted_events.push(links.rewrite_event(event));
     }
 
     html::push_html(&mut output, highlighted_events.into_iter());
-    output
+    let html = match options.sanitize {
+        SanitizeMode::Strict => SANITIZER.clean(&output).to_string(),
+        SanitizeMode::Off => output,
+    };
+    let mut assets = links.assets;
+    assets.sort();
+    assets.dedup();
+    Rendered { html, toc, sections, assets, front_matter, references }
+}
+
+/// Record every link and image with its line, plus references that did not
+/// resolve: `[^label]` footnotes (left as plain text by the parser) and
+/// `[text][label]` links from the broken link callback.
+fn collect_references(
+    input: &str,
+    parsed: &[(Event, Range<usize>)],
+    undefined_labels: Vec<(Range<usize>, String)>,
+) -> Vec<Reference> {
+    let line_starts: Vec<usize> =
+        std::iter::once(0).chain(input.match_indices('\n').map(|(i, _)| i + 1)).collect();
+    let line_of = |offset: usize| line_starts.partition_point(|&start| start <= offset);
+
+    // (source offset, kind, target), sorted into source order at the end.
+    let mut references: Vec<(usize, ReferenceKind, String)> = undefined_labels
+        .into_iter()
+        .map(|(span, label)| (span.start, ReferenceKind::UndefinedLabel, label))
+        .collect();
+
+    // Consecutive text events, joined so `[`, `^label` and `]` are seen together.
+    let mut run: Vec<(&str, usize)> = Vec::new();
+    let flush = |run: &mut Vec<(&str, usize)>, references: &mut Vec<(usize, ReferenceKind, String)>| {
+        let text: String = run.iter().map(|(t, _)| *t).collect();
+        let mut piece_ends = run.iter().scan(0, |end, (t, offset)| {
+            *end += t.len();
+            Some((*end, *offset))
+        });
+        let mut current = piece_ends.next();
+        for (i, _) in text.match_indices("[^") {
+            let Some(close) = text[i..].find(']') else { continue };
+            let label = &text[i + 2..i + close];
+            if label.is_empty() || label.contains(char::is_whitespace) {
+                continue;
+            }
+            while let Some((end, _)) = current
+                && end <= i
+            {
+                current = piece_ends.next();
+            }
+            if let Some((_, offset)) = current {
+                references.push((offset, ReferenceKind::UndefinedFootnote, label.to_string()));
+            }
+        }
+        run.clear();
+    };
+
+    // Text in code blocks is shown verbatim, so `[^a-z]` there is not a footnote.
+    let mut in_code_block = false;
+    for (event, range) in parsed {
+        match event {
+            Event::Text(_) if in_code_block => continue,
+            Event::Text(text) => {
+                run.push((text, range.start));
+                continue;
+            }
+            Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+            Event::End(TagEnd::CodeBlock) => in_code_block = false,
+            Event::Start(Tag::Link { dest_url, .. }) => {
+                references.push((range.start, ReferenceKind::Link, dest_url.to_string()))
+            }
+            Event::Start(Tag::Image { dest_url, .. }) => {
+                references.push((range.start, ReferenceKind::Image, dest_url.to_string()))
+            }
+            _ => {}
+        }
+        flush(&mut run, &mut references);
+    }
+    flush(&mut run, &mut references);
+    references.sort_by_key(|(offset, ..)| *offset);
+    references.into_iter().map(|(offset, kind, target)| Reference { line: line_of(offset), kind, target }).collect()
+}
+
+/// Remove the front matter block from the event stream, returning its kind
+/// and raw text separately.
+fn take_metadata(events: Vec<Event<'_>>) -> (Vec<Event<'_>>, Option<(MetadataBlockKind, String)>) {
+    let mut metadata: Option<(MetadataBlockKind, String)> = None;
+    let mut in_block = false;
+    let events = events
+        .into_iter()
+        .filter(|event| match event {
+            Event::Start(Tag::MetadataBlock(kind)) => {
+                metadata = Some((*kind, String::new()));
+                in_block = true;
+                false
+            }
+            Event::End(TagEnd::MetadataBlock(_)) => {
+                in_block = false;
+                false
+            }
+            Event::Text(text) if in_block => {
+                if let Some((_, buf)) = &mut metadata {
+                    buf.push_str(text);
+                }
+                false
+            }
+            _ => true,
+        })
+        .collect();
+    (events, metadata)
+}
+
+/// Give every heading a GitHub-compatible slug `id` and prepend a hover anchor
+/// link. Returns the rewritten events and the document's heading outline.
+fn anchor_headings(events: Vec<Event<'_>>) -> (Vec<Event<'_>>, Vec<TocEntry>) {
+    let mut slugger = Slugger::default();
+    let mut toc = Vec::new();
+    let mut out = Vec::with_capacity(events.len());
+    let mut heading: Option<(usize, String)> = None; // (index of Start event in `out`, text)
+
+    for event in events {
+        match &event {
+            Event::Start(Tag::Heading { .. }) => heading = Some((out.len(), String::new())),
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, buf)) = heading.as_mut() {
+                    buf.push_str(text);
+                }
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                if let Some((start, text)) = heading.take()
+                    && let Event::Start(Tag::Heading { level, id, classes, attrs }) = &mut out[start]
+                {
+                    let slug = id.clone().map(|i| i.to_string()).unwrap_or_else(|| slugger.slug(&text));
+                    let anchor = format!("<a class=\"anchor\" href=\"#{slug}\" aria-hidden=\"true\">#</a>");
+                    toc.push(TocEntry { level: *level as u8, text: text.trim().to_string(), slug: slug.clone() });
+                    out[start] = Event::Start(Tag::Heading {
+                        level: *level,
+                        id: Some(CowStr::from(slug)),
+                        classes: std::mem::take(classes),
+                        attrs: std::mem::take(attrs),
+                    });
+                    out.insert(start + 1, Event::InlineHtml(CowStr::from(anchor)));
+                }
+            }
+            _ => {}
+        }
+        out.push(event);
+    }
+    (out, toc)
+}
+
+/// Split the document's text into one section per heading (plus any leading
+/// text), with whitespace between blocks. `toc` must come from `anchor_headings`
+/// on the same events.
+fn collect_sections(events: &[Event<'_>], toc: &[TocEntry]) -> Vec<Section> {
+    let mut headings = toc.iter();
+    let mut sections = vec![Section::default()];
+    let mut in_heading = false;
+
+    for event in events {
+        let current = sections.last_mut().unwrap();
+        match event {
+            Event::Start(Tag::Heading { .. }) => {
+                in_heading = true;
+                sections.push(Section { heading: headings.next().cloned(), text: String::new() });
+            }
+            Event::End(TagEnd::Heading(_)) => in_heading = false,
+            _ if in_heading => {}
+            Event::Text(text) | Event::Code(text) => current.text.push_str(text),
+            Event::SoftBreak | Event::HardBreak | Event::End(_)
+                if !current.text.is_empty() && !current.text.ends_with(char::is_whitespace) =>
+            {
+                current.text.push(' ');
+            }
+            _ => {}
+        }
+    }
+
+    for section in &mut sections {
+        section.text.truncate(section.text.trim_end().len());
+    }
+    if sections[0].text.is_empty() {
+        sections.remove(0);
+    }
+    sections
+}
+
+/// Generates heading slugs the way GitHub does (github-slugger): lowercase,
+/// drop punctuation and symbols, spaces become hyphens, and repeated slugs get
+/// `-1`, `-2`, ... suffixes.
+#[derive(Default)]
+pub struct Slugger {
+    occurrences: HashMap<String, usize>,
+}
+
+impl Slugger {
+    pub fn slug(&mut self, text: &str) -> String {
+        let base = slugify(text);
+        let mut result = base.clone();
+        while self.occurrences.contains_key(&result) {
+            let count = self.occurrences.entry(base.clone()).or_insert(0);
+            *count += 1;
+            result = format!("{base}-{count}");
+        }
+        self.occurrences.insert(result.clone(), 0);
+        result
+    }
+}
+
+fn slugify(text: &str) -> String {
+    text.to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            '-' | '_' => Some(c),
+            c if c.is_alphanumeric() => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Rewrites relative image and link URLs for one document, recording the
+/// non-markdown files it references along the way.
+struct LinkRewriter<'a> {
+    doc_path: &'a str,
+    style: LinkStyle,
+    base: &'a str,
+    assets: Vec<String>,
+}
+
+impl LinkRewriter<'_> {
+    /// Rewrite the destination of image and link events so that relative URLs
+    /// resolve against the document's directory rather than `/view/<path>`.
+    /// Links to other markdown files point at their `/view/` page.
+    fn rewrite_event<'e>(&mut self, event: Event<'e>) -> Event<'e> {
+        match event {
+            Event::Start(Tag::Image { link_type, dest_url, title, id }) => {
+                let dest_url = self.rewrite_url(&dest_url).map(CowStr::from).unwrap_or(dest_url);
+                Event::Start(Tag::Image { link_type, dest_url, title, id })
+            }
+            Event::Start(Tag::Link { link_type, dest_url, title, id }) => {
+                let dest_url = self.rewrite_url(&dest_url).map(CowStr::from).unwrap_or(dest_url);
+                Event::Start(Tag::Link { link_type, dest_url, title, id })
+            }
+            other => other,
+        }
+    }
+
+    /// Returns the rewritten URL for a relative reference, or `None` to leave it as-is.
+    fn rewrite_url(&mut self, url: &str) -> Option<String> {
+        if !is_relative_url(url) {
+            return None;
+        }
+        let split = url.find(['?', '#']).unwrap_or(url.len());
+        let (target, suffix) = url.split_at(split);
+        let resolved = resolve_relative(self.doc_path, target)?;
+        let is_markdown = has_markdown_extension(target);
+        if !is_markdown {
+            self.assets.push(resolved.clone());
+        }
+        match (self.style, is_markdown) {
+            (LinkStyle::Server, true) => Some(format!("{}{VIEW_PREFIX}{resolved}{suffix}", self.base)),
+            (LinkStyle::Server, false) => Some(format!("{}{FILE_PREFIX}{resolved}{suffix}", self.base)),
+            (LinkStyle::Relative, true) => {
+                let stem = target.rsplit_once('.').map_or(target, |(stem, _)| stem);
+                Some(format!("{stem}.html{suffix}"))
+            }
+            (LinkStyle::Relative, false) => None,
+        }
+    }
+}
+
+/// True for URLs with no scheme, host or absolute path, which are not bare fragments.
+pub fn is_relative_url(url: &str) -> bool {
+    if url.is_empty() || url.starts_with('#') || url.starts_with('/') {
+        return false;
+    }
+    let scheme_end = url.find([':', '/', '?', '#']);
+    !matches!(scheme_end, Some(i) if url.as_bytes()[i] == b':')
+}
+
+/// Join a relative URL path onto the directory of `doc_path`, normalising
+/// `.` and `..` segments. Returns `None` if the result would escape the root.
+pub fn resolve_relative(doc_path: &str, target: &str) -> Option<String> {
+    let mut segments: Vec<String> = doc_path
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .map(|s| utf8_percent_encode(s, PATH_SEGMENT).to_string())
+        .collect();
+    segments.pop(); // the document's own file name
+
+    for segment in target.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop()?;
+            }
+            s => segments.push(s.to_string()),
+        }
+    }
+    if segments.is_empty() {
+        return None;
+    }
+    Some(segments.join("/"))
 }
 
 fn try_highlight(lang: &str, code: &str) -> String {
@@ -69,11 +596,40 @@ fn try_highlight(lang: &str, code: &str) -> String {
     }
 }
 
-fn plain_code_block(lang: &str, code: &str) -> String {
-    let escaped = code
-        .replace('&', "&amp;")
+/// Render LaTeX to MathML, keeping the source as an annotation. Invalid input
+/// renders as an inline `<merror>` rather than failing the document.
+fn render_math(tex: &str, display_mode: DisplayMode) -> String {
+    let storage = pulldown_latex::Storage::new();
+    let parser = pulldown_latex::Parser::new(tex, &storage);
+    let config = pulldown_latex::RenderConfig {
+        display_mode,
+        ..pulldown_latex::RenderConfig::with_annotation(tex)
+    };
+    let mut mathml = String::new();
+    match pulldown_latex::push_mathml(&mut mathml, parser, config) {
+        Ok(()) => mathml,
+        Err(_) => format!("<code>{}</code>", escape_code(tex)),
+    }
+}
+
+/// A diagram container; the bundled mermaid renderer replaces its text with SVG client-side.
+fn mermaid_block(code: &str) -> String {
+    format!("<pre class=\"mermaid\">{}</pre>\n", escape_code(code))
+}
+
+fn escape_code(code: &str) -> String {
+    code.replace('&', "&amp;")
         .replace('<', "&lt;")
-        .replace('>', "&gt;");
+        .replace('>', "&gt;")
+}
+
+/// Escape text for use in HTML content or a double-quoted attribute.
+pub fn escape_html(s: &str) -> String {
+    escape_code(s).replace('"', "&quot;")
+}
+
+fn plain_code_block(lang: &str, code: &str) -> String {
+    let escaped = escape_code(code);
     let lang_valid = !lang.is_empty()
         && lang
             .chars()
@@ -91,46 +647,47 @@ mod tests {
 
     #[test]
     fn renders_paragraph() {
-        let html = render_markdown("Hello, world!");
+        let html = render_markdown("Hello, world!", "test.md", &RenderOptions::default()).html;
         assert_eq!(html.trim(), "<p>Hello, world!</p>");
     }
 
     #[test]
     fn renders_heading() {
-        let html = render_markdown("# Title");
-        assert_eq!(html.trim(), "<h1>Title</h1>");
+        let html = render_markdown("# Title", "test.md", &RenderOptions::default()).html;
+        assert!(html.starts_with(r##"<h1 id="user-content-title"><a class="anchor" href="#title""##));
+        assert!(html.trim().ends_with("#</a>Title</h1>"));
     }
 
     #[test]
     fn renders_gfm_table() {
         let input = "| A | B |\n|---|---|\n| 1 | 2 |";
-        let html = render_markdown(input);
+        let html = render_markdown(input, "test.md", &RenderOptions::default()).html;
         assert!(html.contains("<table>"));
         assert!(html.contains("<td>1</td>"));
     }
 
     #[test]
     fn renders_strikethrough() {
-        let html = render_markdown("~~deleted~~");
+        let html = render_markdown("~~deleted~~", "test.md", &RenderOptions::default()).html;
         assert!(html.contains("<del>deleted</del>"));
     }
 
     #[test]
     fn renders_tasklist() {
-        let html = render_markdown("- [x] done\n- [ ] todo");
+        let html = render_markdown("- [x] done\n- [ ] todo", "test.md", &RenderOptions::default()).html;
         assert!(html.contains(r#"type="checkbox""#));
     }
 
     #[test]
     fn renders_empty_input() {
-        let html = render_markdown("");
+        let html = render_markdown("", "test.md", &RenderOptions::default()).html;
         assert_eq!(html, "");
     }
 
     #[test]
     fn highlights_rust_code_block() {
         let input = "```rust\nfn main() {}\n```";
-        let html = render_markdown(input);
+        let html = render_markdown(input, "test.md", &RenderOptions::default()).html;
         assert!(html.contains("<pre class=\"highlight\">"));
         assert!(html.contains("main"));
     }
@@ -138,7 +695,7 @@ mod tests {
     #[test]
     fn highlights_python_code_block() {
         let input = "```python\ndef hello():\n    pass\n```";
-        let html = render_markdown(input);
+        let html = render_markdown(input, "test.md", &RenderOptions::default()).html;
         assert!(html.contains("<pre class=\"highlight\">"));
         assert!(html.contains("hello"));
     }
@@ -146,7 +703,7 @@ mod tests {
     #[test]
     fn unrecognized_language_falls_back() {
         let input = "```unknownlang\nsome code\n```";
-        let html = render_markdown(input);
+        let html = render_markdown(input, "test.md", &RenderOptions::default()).html;
         assert!(html.contains("some code"));
         assert!(html.contains("<pre"));
     }
@@ -154,7 +711,7 @@ mod tests {
     #[test]
     fn indented_code_block_no_highlight() {
         let input = "    indented code";
-        let html = render_markdown(input);
+        let html = render_markdown(input, "test.md", &RenderOptions::default()).html;
         assert!(html.contains("<pre><code>"));
         assert!(html.contains("indented code"));
     }
@@ -162,7 +719,7 @@ mod tests {
     #[test]
     fn fenced_block_no_language() {
         let input = "```\nplain code\n```";
-        let html = render_markdown(input);
+        let html = render_markdown(input, "test.md", &RenderOptions::default()).html;
         assert!(html.contains("plain code"));
         assert!(html.contains("<pre"));
     }
@@ -170,9 +727,262 @@ mod tests {
     #[test]
     fn lang_attribute_is_escaped() {
         let input = "```foo\"onmouseover=\"alert(1)\ncode\n```";
-        let html = render_markdown(input);
+        let html = render_markdown(input, "test.md", &RenderOptions::default()).html;
         assert!(!html.contains("onmouseover"));
         assert!(!html.contains("class="));
         assert!(html.contains("<pre><code>"));
     }
+
+    #[test]
+    fn relative_image_resolves_against_document_dir() {
+        let html = render_markdown("![diagram](./img/arch.png)", "docs/design.md", &RenderOptions::default()).html;
+        assert!(html.contains(r#"src="/file/docs/img/arch.png""#));
+    }
+
+    #[test]
+    fn parent_relative_link_is_normalised() {
+        let html = render_markdown("[spec](../specs/api.pdf#page=2)", "docs/guide/intro.md", &RenderOptions::default()).html;
+        assert!(html.contains(r#"href="/file/docs/specs/api.pdf#page=2""#));
+    }
+
+    #[test]
+    fn document_dir_is_percent_encoded() {
+        let html = render_markdown("![x](a.png)", "my docs/page.md", &RenderOptions::default()).html;
+        assert!(html.contains(r#"src="/file/my%20docs/a.png""#));
+    }
+
+    #[test]
+    fn absolute_and_external_urls_untouched() {
+        let html = render_markdown(
+            "[a](https://example.com/x.png) [b](/abs.png) [c](#top) [d](mailto:me@example.com)",
+            "docs/page.md",
+            &RenderOptions::default(),
+        ).html;
+        assert!(html.contains(r#"href="https://example.com/x.png""#));
+        assert!(html.contains(r#"href="/abs.png""#));
+        assert!(html.contains(r##"href="#top""##));
+        assert!(html.contains(r#"href="mailto:me@example.com""#));
+    }
+
+    #[test]
+    fn url_escaping_root_is_untouched() {
+        let html = render_markdown("![x](../../secret.png)", "docs/page.md", &RenderOptions::default()).html;
+        assert!(html.contains(r#"src="../../secret.png""#));
+    }
+
+    #[test]
+    fn relative_markdown_link_becomes_view_url() {
+        let html = render_markdown("[setup](../guide/setup.md#install)", "docs/intro/index.md", &RenderOptions::default()).html;
+        assert!(html.contains(r#"href="/view/docs/guide/setup.md#install""#));
+    }
+
+    #[test]
+    fn sibling_markdown_link_becomes_view_url() {
+        let html = render_markdown("[notes](notes.md)", "README.md", &RenderOptions::default()).html;
+        assert!(html.contains(r#"href="/view/notes.md""#));
+    }
+
+    #[test]
+    fn mounted_root_prefixes_server_links() {
+        let options = RenderOptions { base: "/docs".into(), ..Default::default() };
+        let html = render_markdown("[notes](notes.md) ![a](img/a.png)", "guide/README.md", &options).html;
+        assert!(html.contains(r#"href="/docs/view/guide/notes.md""#));
+        assert!(html.contains(r#"src="/docs/file/guide/img/a.png""#));
+    }
+
+    #[test]
+    fn script_tag_is_stripped() {
+        let html = render_markdown("<script>alert(1)</script>\n\ntext", "test.md", &RenderOptions::default()).html;
+        assert!(!html.contains("<script"));
+        assert!(!html.contains("alert(1)"));
+        assert!(html.contains("text"));
+    }
+
+    #[test]
+    fn event_handler_attribute_is_stripped() {
+        let html = render_markdown(r#"<img src="x.png" onerror="alert(1)">"#, "test.md", &RenderOptions::default()).html;
+        assert!(!html.contains("onerror"));
+        assert!(html.contains("<img"));
+    }
+
+    #[test]
+    fn javascript_url_is_stripped() {
+        let html = render_markdown("[click](javascript:alert(1))", "test.md", &RenderOptions::default()).html;
+        assert!(!html.contains("javascript:"));
+    }
+
+    #[test]
+    fn highlighting_survives_sanitization() {
+        let html = render_markdown("```rust\nfn main() {}\n```", "test.md", &RenderOptions::default()).html;
+        assert!(html.contains("<pre class=\"highlight\">"));
+        assert!(html.contains("<span class="));
+    }
+
+    #[test]
+    fn tasklist_and_alignment_survive_sanitization() {
+        let html = render_markdown("- [x] done\n\n| A |\n|:-:|\n| 1 |", "test.md", &RenderOptions::default()).html;
+        assert!(html.contains(r#"type="checkbox""#));
+        assert!(html.contains("text-align: center") || html.contains("text-align:center"));
+    }
+
+    #[test]
+    fn math_style_properties_stay_on_math() {
+        let html = render_markdown(
+            r#"<table><tr><td style="text-align: right; color: red; height: 9em">x</td></tr></table>
+
+<p style="color: red">y</p> <math><mi style="color: red">z</mi></math>"#,
+            "test.md",
+            &RenderOptions::default(),
+        )
+        .html;
+        assert!(html.contains(r#"<td style="text-align:right">"#), "{html}");
+        assert!(html.contains("<p>y</p>"));
+        assert!(html.contains(r#"<mi style="color:red">"#));
+    }
+
+    #[test]
+    fn sanitize_off_passes_raw_html() {
+        let options = RenderOptions { sanitize: SanitizeMode::Off, ..Default::default() };
+        let html = render_markdown("<div onclick=\"x()\">hi</div>", "test.md", &options).html;
+        assert!(html.contains("onclick"));
+    }
+
+    #[test]
+    fn slugs_follow_github_rules() {
+        let mut slugger = Slugger::default();
+        assert_eq!(slugger.slug("Hello, World!"), "hello-world");
+        assert_eq!(slugger.slug("Setup `cargo` & Run"), "setup-cargo--run");
+        assert_eq!(slugger.slug("snake_case and-dashes"), "snake_case-and-dashes");
+        assert_eq!(slugger.slug("Über Café 日本語"), "über-café-日本語");
+        assert_eq!(slugger.slug("Emoji 🎉 party"), "emoji--party");
+    }
+
+    #[test]
+    fn duplicate_slugs_get_suffixes() {
+        let mut slugger = Slugger::default();
+        assert_eq!(slugger.slug("Intro"), "intro");
+        assert_eq!(slugger.slug("Intro"), "intro-1");
+        assert_eq!(slugger.slug("Intro"), "intro-2");
+        assert_eq!(slugger.slug("Intro 1"), "intro-1-1");
+    }
+
+    #[test]
+    fn headings_get_ids_and_anchor_links() {
+        let html = render_markdown("## Setup\n\n## Setup", "test.md", &RenderOptions::default()).html;
+        assert!(html.contains(r#"<h2 id="user-content-setup">"#));
+        assert!(html.contains(r##"href="#setup""##));
+        assert!(html.contains(r#"<h2 id="user-content-setup-1">"#));
+    }
+
+    #[test]
+    fn raw_html_ids_cannot_clobber_the_page() {
+        let html = render_markdown("<div id=\"file-tree\">x</div>\n\n## Search", "test.md", &RenderOptions::default()).html;
+        assert!(html.contains(r#"<div id="user-content-file-tree">"#), "{html}");
+        assert!(html.contains(r#"<h2 id="user-content-search">"#));
+    }
+
+    #[test]
+    fn heading_slug_includes_inline_code() {
+        let html = render_markdown("# The `render` step", "test.md", &RenderOptions::default()).html;
+        assert!(html.contains(r#"id="user-content-the-render-step""#));
+    }
+
+    #[test]
+    fn extracts_heading_outline() {
+        let rendered = render_markdown("# Guide\n\ntext\n\n## Install `marpe`\n\n### Setup\n", "test.md", &RenderOptions::default());
+        assert_eq!(
+            rendered.toc,
+            vec![
+                TocEntry { level: 1, text: "Guide".into(), slug: "guide".into() },
+                TocEntry { level: 2, text: "Install marpe".into(), slug: "install-marpe".into() },
+                TocEntry { level: 3, text: "Setup".into(), slug: "setup".into() },
+            ]
+        );
+    }
+
+    #[test]
+    fn splits_text_into_sections() {
+        let input = "Intro text.\n\n# Install\n\nRun `cargo install`.\n\n```sh\nmarpe --open\n```\n\n## Usage\nline one\nline two";
+        let sections = render_markdown(input, "test.md", &RenderOptions::default()).sections;
+        assert_eq!(sections.len(), 3);
+        assert!(sections[0].heading.is_none());
+        assert_eq!(sections[0].text, "Intro text.");
+        assert_eq!(sections[1].heading.as_ref().unwrap().slug, "install");
+        assert_eq!(sections[1].text, "Run cargo install. marpe --open");
+        assert_eq!(sections[2].heading.as_ref().unwrap().text, "Usage");
+        assert_eq!(sections[2].text, "line one line two");
+    }
+
+    #[test]
+    fn mermaid_fence_becomes_diagram_container() {
+        let input = "```mermaid\ngraph TD\n  A-->B & C\n```";
+        let html = render_markdown(input, "test.md", &RenderOptions::default()).html;
+        assert!(html.contains(r#"<pre class="mermaid">graph TD"#));
+        assert!(html.contains("A--&gt;B &amp; C"));
+        assert!(!html.contains("highlight"));
+    }
+
+    #[test]
+    fn inline_math_renders_as_mathml() {
+        let html = render_markdown("Area is $x^2$.", "test.md", &RenderOptions::default()).html;
+        assert!(html.contains(r#"<math display="inline">"#));
+        assert!(html.contains("<msup>"));
+        assert!(html.contains(r#"<annotation encoding="application/x-tex">x^2</annotation>"#));
+    }
+
+    #[test]
+    fn display_math_renders_as_block() {
+        let html = render_markdown("$$\\frac{a}{b}$$", "test.md", &RenderOptions::default()).html;
+        assert!(html.contains(r#"<math display="block">"#));
+        assert!(html.contains("<mfrac>"));
+    }
+
+    #[test]
+    fn raw_math_markup_is_still_sanitized() {
+        let input = r#"<math><mi onclick="x()">a</mi><mtext><img src=x onerror=alert(1)><script>alert(2)</script></mtext></math>"#;
+        let html = render_markdown(input, "test.md", &RenderOptions::default()).html;
+        assert!(!html.contains("onclick"));
+        assert!(!html.contains("onerror"));
+        assert!(!html.contains("<script"));
+    }
+
+    #[test]
+    fn records_referenced_assets() {
+        let input = "![a](img/a.png) [b](../b.pdf) [c](other.md) ![a again](./img/a.png) [d](https://x.com/d.png)";
+        let assets = render_markdown(input, "docs/page.md", &RenderOptions::default()).assets;
+        assert_eq!(assets, vec!["b.pdf", "docs/img/a.png"]);
+    }
+
+    #[test]
+    fn relative_link_style_keeps_paths_and_swaps_extension() {
+        let options = RenderOptions { links: LinkStyle::Relative, ..Default::default() };
+        let html = render_markdown("[setup](../guide/setup.md#install) ![x](img/x.png)", "docs/intro/index.md", &options).html;
+        assert!(html.contains(r#"href="../guide/setup.html#install""#));
+        assert!(html.contains(r#"src="img/x.png""#));
+    }
+
+    #[test]
+    fn yaml_front_matter_becomes_card() {
+        let doc = render_markdown("---\ntitle: Notes\ntags: [a, b]\n---\n# Body\n", "x.md", &RenderOptions::default());
+        assert_eq!(doc.title(), Some("Notes"));
+        assert!(!doc.html.contains("<hr"));
+        assert!(doc.html.starts_with(r#"<div class="front-matter"><dl><dt>title</dt><dd>Notes</dd>"#));
+        assert_eq!(doc.toc.len(), 1);
+        assert!(!doc.sections.iter().any(|s| s.text.contains("tags")));
+    }
+
+    #[test]
+    fn toml_front_matter_is_parsed() {
+        let doc = render_markdown("+++\ntitle = \"Notes\"\n+++\ntext\n", "x.md", &RenderOptions::default());
+        assert_eq!(doc.title(), Some("Notes"));
+        assert!(!doc.html.contains("+++"));
+    }
+
+    #[test]
+    fn invalid_front_matter_shows_error() {
+        let doc = render_markdown("---\ntitle: [oops\n---\ntext\n", "x.md", &RenderOptions::default());
+        assert!(doc.front_matter.is_none());
+        assert!(doc.html.contains("front-matter-error"));
+        assert!(doc.html.contains("<p>text</p>"));
+    }
 }
@@ -0,0 +1,280 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Range;
+
+use crate::render::{Rendered, Section, escape_html};
+
+/// Maximum number of hits returned for a query.
+pub const MAX_HITS: usize = 30;
+/// Approximate snippet length, in characters.
+const SNIPPET_CHARS: usize = 160;
+/// Term frequency weight for words that appear in a section's heading.
+const HEADING_WEIGHT: u32 = 3;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    /// Name of the root the file belongs to; empty unless several roots are served.
+    pub root: String,
+    pub path: String,
+    pub heading: Option<String>,
+    pub slug: Option<String>,
+    /// HTML-escaped excerpt with matched words wrapped in `<mark>`.
+    pub snippet: String,
+    pub score: f32,
+}
+
+/// Inverted index from lowercase words to the document sections containing them.
+/// Section text itself is not stored; it is read from the rendered documents at
+/// query time.
+#[derive(Default)]
+pub struct SearchIndex {
+    postings: HashMap<String, HashMap<String, Vec<(usize, u32)>>>, // term -> path -> [(section, tf)]
+    doc_terms: HashMap<String, Vec<String>>,                       // path -> distinct terms
+}
+
+impl SearchIndex {
+    pub fn build(files: &BTreeMap<String, Rendered>) -> Self {
+        let mut index = Self::default();
+        for (path, doc) in files {
+            index.insert(path, &doc.sections);
+        }
+        index
+    }
+
+    /// Index a document's sections, replacing any previous entry for `path`.
+    pub fn insert(&mut self, path: &str, sections: &[Section]) {
+        self.remove(path);
+
+        let mut per_term: HashMap<String, Vec<(usize, u32)>> = HashMap::new();
+        for (i, section) in sections.iter().enumerate() {
+            let mut counts: HashMap<String, u32> = HashMap::new();
+            if let Some(heading) = &section.heading {
+                for (_, word) in words(&heading.text) {
+                    *counts.entry(word).or_default() += HEADING_WEIGHT;
+                }
+            }
+            for (_, word) in words(&section.text) {
+                *counts.entry(word).or_default() += 1;
+            }
+            for (term, tf) in counts {
+                per_term.entry(term).or_default().push((i, tf));
+            }
+        }
+
+        let terms: Vec<String> = per_term.keys().cloned().collect();
+        for (term, hits) in per_term {
+            self.postings.entry(term).or_default().insert(path.to_string(), hits);
+        }
+        self.doc_terms.insert(path.to_string(), terms);
+    }
+
+    pub fn remove(&mut self, path: &str) {
+        let Some(terms) = self.doc_terms.remove(path) else { return };
+        for term in terms {
+            if let Some(docs) = self.postings.get_mut(&term) {
+                docs.remove(path);
+                if docs.is_empty() {
+                    self.postings.remove(&term);
+                }
+            }
+        }
+    }
+
+    /// Find sections matching every word of `query` (the last word may be a
+    /// prefix, for search-as-you-type), ranked by tf-idf.
+    pub fn search(&self, query: &str, files: &BTreeMap<String, Rendered>) -> Vec<SearchHit> {
+        let query_terms: Vec<String> = words(query).map(|(_, w)| w).collect();
+        if query_terms.is_empty() {
+            return Vec::new();
+        }
+        let doc_count = self.doc_terms.len().max(1) as f32;
+
+        // Per query term: path -> section -> tf
+        let mut matches: Vec<HashMap<&str, HashMap<usize, u32>>> = Vec::new();
+        for (i, term) in query_terms.iter().enumerate() {
+            let is_last = i == query_terms.len() - 1;
+            let mut by_doc: HashMap<&str, HashMap<usize, u32>> = HashMap::new();
+            for (indexed, docs) in &self.postings {
+                let hit = indexed == term || (is_last && indexed.starts_with(term.as_str()));
+                if !hit {
+                    continue;
+                }
+                for (path, sections) in docs {
+                    let entry = by_doc.entry(path.as_str()).or_default();
+                    for &(section, tf) in sections {
+                        *entry.entry(section).or_default() += tf;
+                    }
+                }
+            }
+            matches.push(by_doc);
+        }
+
+        // Documents must contain every query term somewhere.
+        let mut candidates: HashSet<&str> = matches[0].keys().copied().collect();
+        for by_doc in &matches[1..] {
+            candidates.retain(|path| by_doc.contains_key(path));
+        }
+
+        let mut scored: HashMap<(&str, usize), f32> = HashMap::new();
+        for by_doc in &matches {
+            let idf = (1.0 + doc_count / by_doc.len() as f32).ln();
+            for (path, sections) in by_doc {
+                if !candidates.contains(path) {
+                    continue;
+                }
+                for (&section, &tf) in sections {
+                    *scored.entry((path, section)).or_default() += (1.0 + (tf as f32).ln()) * idf;
+                }
+            }
+        }
+
+        let mut ranked: Vec<_> = scored.into_iter().collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        ranked
+            .into_iter()
+            .take(MAX_HITS)
+            .filter_map(|((path, idx), score)| {
+                let section = files.get(path)?.sections.get(idx)?;
+                Some(SearchHit {
+                    root: String::new(),
+                    path: path.to_string(),
+                    heading: section.heading.as_ref().map(|h| h.text.clone()),
+                    slug: section.heading.as_ref().map(|h| h.slug.clone()),
+                    snippet: snippet(&section.text, &query_terms),
+                    score,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Split text into lowercase alphanumeric words, with their byte ranges.
+fn words(text: &str) -> impl Iterator<Item = (Range<usize>, String)> + '_ {
+    let mut chars = text.char_indices().peekable();
+    std::iter::from_fn(move || {
+        while chars.next_if(|(_, c)| !c.is_alphanumeric()).is_some() {}
+        let (start, _) = *chars.peek()?;
+        let mut end = start;
+        while let Some((i, c)) = chars.next_if(|(_, c)| c.is_alphanumeric()) {
+            end = i + c.len_utf8();
+        }
+        Some((start..end, text[start..end].to_lowercase()))
+    })
+}
+
+fn matches_query(word: &str, query_terms: &[String]) -> bool {
+    let last = query_terms.len() - 1;
+    query_terms
+        .iter()
+        .enumerate()
+        .any(|(i, term)| word == term || (i == last && word.starts_with(term.as_str())))
+}
+
+/// Build an HTML-escaped excerpt of `text` around the first matched word,
+/// wrapping every matched word in `<mark>`.
+fn snippet(text: &str, query_terms: &[String]) -> String {
+    let marked: Vec<Range<usize>> = words(text)
+        .filter(|(_, w)| matches_query(w, query_terms))
+        .map(|(range, _)| range)
+        .collect();
+
+    // Start a little before the first match, on a char boundary.
+    let first = marked.first().map_or(0, |r| r.start);
+    let lead: usize = text[..first].chars().rev().take(SNIPPET_CHARS / 3).map(char::len_utf8).sum();
+    let start = first - lead;
+    let end = start + text[start..].chars().take(SNIPPET_CHARS).map(char::len_utf8).sum::<usize>();
+
+    let mut out = String::new();
+    if start > 0 {
+        out.push('…');
+    }
+    let mut pos = start;
+    for range in marked.iter().filter(|r| r.start >= start && r.end <= end) {
+        out.push_str(&escape_html(&text[pos..range.start]));
+        out.push_str("<mark>");
+        out.push_str(&escape_html(&text[range.clone()]));
+        out.push_str("</mark>");
+        pos = range.end;
+    }
+    out.push_str(&escape_html(&text[pos..end]));
+    if end < text.len() {
+        out.push('…');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::{RenderOptions, render_markdown};
+
+    fn files(docs: &[(&str, &str)]) -> BTreeMap<String, Rendered> {
+        docs.iter()
+            .map(|(path, md)| (path.to_string(), render_markdown(md, path, &RenderOptions::default())))
+            .collect()
+    }
+
+    #[test]
+    fn finds_section_with_heading_context() {
+        let files = files(&[
+            ("a.md", "# Intro\n\nNothing here.\n\n## Install\n\nRun the installer."),
+            ("b.md", "# Other\n\nUnrelated text."),
+        ]);
+        let index = SearchIndex::build(&files);
+        let hits = index.search("installer", &files);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].path, "a.md");
+        assert_eq!(hits[0].heading.as_deref(), Some("Install"));
+        assert_eq!(hits[0].slug.as_deref(), Some("install"));
+        assert!(hits[0].snippet.contains("<mark>installer</mark>"));
+    }
+
+    #[test]
+    fn all_terms_must_match_and_last_is_prefix() {
+        let files = files(&[
+            ("a.md", "watcher debounce window"),
+            ("b.md", "watcher only"),
+        ]);
+        let index = SearchIndex::build(&files);
+        let hits = index.search("Watcher deb", &files);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].path, "a.md");
+    }
+
+    #[test]
+    fn heading_matches_rank_higher() {
+        let files = files(&[
+            ("body.md", "# Notes\n\nsome text about tokens"),
+            ("head.md", "# Tokens\n\nsome text"),
+        ]);
+        let index = SearchIndex::build(&files);
+        let hits = index.search("tokens", &files);
+        assert_eq!(hits[0].path, "head.md");
+    }
+
+    #[test]
+    fn remove_and_reinsert() {
+        let mut files = files(&[("a.md", "alpha")]);
+        let mut index = SearchIndex::build(&files);
+        index.remove("a.md");
+        assert!(index.search("alpha", &files).is_empty());
+
+        files.insert("a.md".into(), render_markdown("beta", "a.md", &RenderOptions::default()));
+        index.insert("a.md", &files["a.md"].sections);
+        assert!(index.search("alpha", &files).is_empty());
+        assert_eq!(index.search("beta", &files).len(), 1);
+    }
+
+    #[test]
+    fn snippet_is_escaped() {
+        let terms = vec!["tag".to_string()];
+        assert_eq!(snippet("a <b> tag & more", &terms), "a &lt;b&gt; <mark>tag</mark> &amp; more");
+    }
+
+    #[test]
+    fn empty_query_returns_nothing() {
+        let files = files(&[("a.md", "text")]);
+        let index = SearchIndex::build(&files);
+        assert!(index.search("  !! ", &files).is_empty());
+    }
+}
@@ -1,12 +1,17 @@
+use axum::http::HeaderMap;
 use serde::Serialize;
 use std::collections::BTreeMap;
 use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::{broadcast, RwLock};
 
-use crate::assets::PageShell;
+use crate::assets::{MarkdownStyle, PageShell};
+use crate::filter::FileFilter;
+use crate::htpasswd::Htpasswd;
+use crate::render::{RenderOptions, Rendered, TocEntry};
+use crate::search::{SearchHit, SearchIndex};
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 #[serde(tag = "type", content = "path")]
 #[allow(clippy::enum_variant_names)]
 pub enum SseEvent {
@@ -15,55 +20,137 @@ pub enum SseEvent {
     FileRemoved(String),
 }
 
+/// An [`SseEvent`] tagged with the name of the root it happened in.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RootEvent {
+    pub root: String,
+    #[serde(flatten)]
+    pub event: SseEvent,
+}
+
+/// Every served root, in command line order. A single root is served at `/`;
+/// with several, each is mounted under `/<name>` (see [`AppState::base`]).
+pub struct Roots {
+    pub roots: Vec<Arc<AppState>>,
+    /// The htpasswd user list guarding each root, if any, in the same order.
+    pub logins: Vec<Option<Arc<Htpasswd>>>,
+}
+
+impl Roots {
+    pub fn new(roots: Vec<Arc<AppState>>, logins: Vec<Option<Arc<Htpasswd>>>) -> Arc<Self> {
+        debug_assert_eq!(roots.len(), logins.len(), "one login per root");
+        Arc::new(Self { roots, logins })
+    }
+
+    /// The roots a request may see: those without a login, and those whose
+    /// user list accepts the request's credentials.
+    pub async fn visible(&self, headers: &HeaderMap) -> Vec<&Arc<AppState>> {
+        let mut visible = Vec::new();
+        for (root, login) in self.roots.iter().zip(&self.logins) {
+            match login {
+                Some(login) if !login.allows(headers).await => {}
+                _ => visible.push(root),
+            }
+        }
+        visible
+    }
+
+    /// True if roots are mounted under their names rather than served at `/`.
+    pub fn is_mounted(&self) -> bool {
+        self.roots.iter().any(|root| !root.base().is_empty())
+    }
+}
+
 pub struct AppState {
     pub root: PathBuf,
-    pub files: RwLock<BTreeMap<String, String>>, // relative path (as string) -> rendered HTML
+    pub files: RwLock<BTreeMap<String, Rendered>>, // relative path (as string) -> rendered document
+    pub search_index: RwLock<SearchIndex>,
     pub tx: broadcast::Sender<SseEvent>,
     pub syntax_css_light: String,
     pub syntax_css_dark: String,
     pub page_shell: PageShell,
+    pub render_options: RenderOptions,
+    pub filter: FileFilter,
 }
 
 impl AppState {
-    pub fn new(root: PathBuf, syntax_theme_light: &str, syntax_theme_dark: &str) -> Arc<Self> {
+    pub fn new(
+        root: PathBuf,
+        syntax_theme_light: &str,
+        syntax_theme_dark: &str,
+        render_options: RenderOptions,
+        filter: FileFilter,
+        style: MarkdownStyle,
+    ) -> Arc<Self> {
         let (tx, _rx) = broadcast::channel(64);
-
-        let mut ts = syntect::highlighting::ThemeSet::load_defaults();
-
-        let monokai_theme = syntect::highlighting::ThemeSet::load_from_reader(
-            &mut std::io::Cursor::new(include_str!("assets/Monokai.tmtheme"))
-        ).expect("Failed to parse bundled Monokai theme");
-        ts.themes.insert("Monokai".to_string(), monokai_theme);
-
-        let theme_light = &ts.themes.get(syntax_theme_light).unwrap_or_else(|| {
-            eprintln!("Warning: Syntax theme '{}' not found, falling back to InspiredGitHub", syntax_theme_light);
-            &ts.themes["InspiredGitHub"]
-        });
-        let theme_dark = &ts.themes.get(syntax_theme_dark).unwrap_or_else(|| {
-            eprintln!("Warning: Syntax theme '{}' not found, falling back to Monokai", syntax_theme_dark);
-            &ts.themes["Monokai"]
-        });
-
-        let syntax_css_light = syntect::html::css_for_theme_with_class_style(theme_light, syntect::html::ClassStyle::Spaced).unwrap();
-        let syntax_css_light = syntax_css_light.lines()
-            .map(|l| if l.starts_with('.') { format!(".theme-light {}", l) } else { l.to_string() })
-            .collect::<Vec<_>>().join("\n");
-
-        let syntax_css_dark = syntect::html::css_for_theme_with_class_style(theme_dark, syntect::html::ClassStyle::Spaced).unwrap();
-        let syntax_css_dark = syntax_css_dark.lines()
-            .map(|l| if l.starts_with('.') { format!(".theme-dark {}", l) } else { l.to_string() })
-            .collect::<Vec<_>>().join("\n");
+        let (syntax_css_light, syntax_css_dark) = syntax_css(syntax_theme_light, syntax_theme_dark);
+        let page_shell = match filter.single_file() {
+            Some(_) => PageShell::new(style).single_file(),
+            None => PageShell::new(style),
+        };
 
         Arc::new(Self {
             root,
             files: RwLock::new(BTreeMap::new()),
+            search_index: RwLock::new(SearchIndex::default()),
             tx,
             syntax_css_light,
             syntax_css_dark,
-            page_shell: PageShell::new(),
+            page_shell,
+            render_options,
+            filter,
         })
     }
 
+    /// URL prefix this root is mounted under, e.g. `/docs`; empty for a single root.
+    pub fn base(&self) -> &str {
+        &self.render_options.base
+    }
+
+    /// Name of this root in URLs and SSE events; empty for a single root.
+    pub fn name(&self) -> &str {
+        self.base().trim_start_matches('/')
+    }
+
+    /// Replace all files (e.g. after initial discovery) and rebuild the search index.
+    pub async fn load(&self, docs: BTreeMap<String, Rendered>) {
+        let mut files = self.files.write().await;
+        *self.search_index.write().await = SearchIndex::build(&docs);
+        *files = docs;
+    }
+
+    /// Replace the files whose paths start with `prefix` (all files for `""`)
+    /// with a fresh discovery of that part of the tree, returning what
+    /// changed: removals first, then additions and changes in path order.
+    pub async fn sync(&self, prefix: &str, docs: BTreeMap<String, Rendered>) -> Vec<SseEvent> {
+        let mut files = self.files.write().await;
+        let mut search_index = self.search_index.write().await;
+        let gone: Vec<String> = files
+            .range(prefix.to_string()..)
+            .map(|(path, _)| path)
+            .take_while(|path| path.starts_with(prefix))
+            .filter(|path| !docs.contains_key(*path))
+            .cloned()
+            .collect();
+        let mut changes = Vec::new();
+        for path in gone {
+            files.remove(&path);
+            search_index.remove(&path);
+            changes.push(SseEvent::FileRemoved(path));
+        }
+        for (path, doc) in docs {
+            let change = match files.get(&path) {
+                None => SseEvent::FileAdded(path.clone()),
+                Some(old) if old.html != doc.html => SseEvent::FileChanged(path.clone()),
+                Some(_) => continue,
+            };
+            search_index.insert(&path, &doc.sections);
+            files.insert(path, doc);
+            changes.push(change);
+        }
+        changes
+    }
+
     /// Get sorted list of all file paths.
     pub async fn file_list(&self) -> Vec<String> {
         let files = self.files.read().await;
@@ -73,38 +160,117 @@ impl AppState {
     /// Get rendered HTML for a path, if it exists.
     pub async fn get_rendered(&self, path: &str) -> Option<String> {
         let files = self.files.read().await;
-        files.get(path).cloned()
+        files.get(path).map(|doc| doc.html.clone())
+    }
+
+    /// Get the page title and rendered HTML for a path. The title comes from
+    /// the front matter, falling back to the path itself.
+    pub async fn get_page(&self, path: &str) -> Option<(String, String)> {
+        let files = self.files.read().await;
+        files.get(path).map(|doc| (doc.title().unwrap_or(path).to_string(), doc.html.clone()))
+    }
+
+    /// Get the heading outline for a path, if it exists.
+    pub async fn get_toc(&self, path: &str) -> Option<Vec<TocEntry>> {
+        let files = self.files.read().await;
+        files.get(path).map(|doc| doc.toc.clone())
     }
 
     /// Insert or update a rendered file.
-    pub async fn upsert(&self, path: String, html: String) -> bool {
+    pub async fn upsert(&self, path: String, doc: Rendered) -> bool {
         let mut files = self.files.write().await;
+        self.search_index.write().await.insert(&path, &doc.sections);
         let is_new = !files.contains_key(&path);
-        files.insert(path, html);
+        files.insert(path, doc);
         is_new
     }
 
     /// Remove a file. Returns true if it existed.
     pub async fn remove(&self, path: &str) -> bool {
         let mut files = self.files.write().await;
+        self.search_index.write().await.remove(path);
         files.remove(path).is_some()
     }
+
+    /// True if any file path starts with `prefix`, e.g. a directory's `docs/`.
+    pub async fn has_files_under(&self, prefix: &str) -> bool {
+        let files = self.files.read().await;
+        files.range(prefix.to_string()..).next().is_some_and(|(path, _)| path.starts_with(prefix))
+    }
+
+    /// Full-text search across all files.
+    pub async fn search(&self, query: &str) -> Vec<SearchHit> {
+        let files = self.files.read().await;
+        let mut hits = self.search_index.read().await.search(query, &files);
+        for hit in &mut hits {
+            hit.root = self.name().to_string();
+        }
+        hits
+    }
+}
+
+/// Build the light and dark syntax highlighting stylesheets, scoped under
+/// `.theme-light` / `.theme-dark`. Unknown theme names fall back to the defaults.
+pub fn syntax_css(syntax_theme_light: &str, syntax_theme_dark: &str) -> (String, String) {
+    let ts = theme_set();
+
+    let theme_light = &ts.themes.get(syntax_theme_light).unwrap_or_else(|| {
+        eprintln!("Warning: Syntax theme '{}' not found, falling back to InspiredGitHub", syntax_theme_light);
+        &ts.themes["InspiredGitHub"]
+    });
+    let theme_dark = &ts.themes.get(syntax_theme_dark).unwrap_or_else(|| {
+        eprintln!("Warning: Syntax theme '{}' not found, falling back to Monokai", syntax_theme_dark);
+        &ts.themes["Monokai"]
+    });
+
+    let syntax_css_light = syntect::html::css_for_theme_with_class_style(theme_light, syntect::html::ClassStyle::Spaced).unwrap();
+    let syntax_css_light = syntax_css_light.lines()
+        .map(|l| if l.starts_with('.') { format!(".theme-light {}", l) } else { l.to_string() })
+        .collect::<Vec<_>>().join("\n");
+
+    let syntax_css_dark = syntect::html::css_for_theme_with_class_style(theme_dark, syntect::html::ClassStyle::Spaced).unwrap();
+    let syntax_css_dark = syntax_css_dark.lines()
+        .map(|l| if l.starts_with('.') { format!(".theme-dark {}", l) } else { l.to_string() })
+        .collect::<Vec<_>>().join("\n");
+
+    (syntax_css_light, syntax_css_dark)
+}
+
+/// Names of the available syntax highlighting themes, sorted.
+pub fn theme_names() -> Vec<String> {
+    theme_set().themes.into_keys().collect()
+}
+
+/// syntect's default themes plus the bundled Monokai.
+fn theme_set() -> syntect::highlighting::ThemeSet {
+    let mut ts = syntect::highlighting::ThemeSet::load_defaults();
+
+    let monokai_theme = syntect::highlighting::ThemeSet::load_from_reader(
+        &mut std::io::Cursor::new(include_str!("assets/Monokai.tmtheme"))
+    ).expect("Failed to parse bundled Monokai theme");
+    ts.themes.insert("Monokai".to_string(), monokai_theme);
+    ts
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::render::render_markdown;
+
+    fn doc(html: &str) -> Rendered {
+        Rendered { html: html.into(), ..Default::default() }
+    }
 
     #[tokio::test]
     async fn new_state_has_empty_file_list() {
-        let state = AppState::new(PathBuf::from("."), "InspiredGitHub", "Monokai");
+        let state = AppState::new(PathBuf::from("."), "InspiredGitHub", "Monokai", RenderOptions::default(), FileFilter::default(), MarkdownStyle::default());
         assert!(state.file_list().await.is_empty());
     }
 
     #[tokio::test]
     async fn upsert_and_get() {
-        let state = AppState::new(PathBuf::from("."), "InspiredGitHub", "Monokai");
-        let is_new = state.upsert("README.md".into(), "<p>hi</p>".into()).await;
+        let state = AppState::new(PathBuf::from("."), "InspiredGitHub", "Monokai", RenderOptions::default(), FileFilter::default(), MarkdownStyle::default());
+        let is_new = state.upsert("README.md".into(), doc("<p>hi</p>")).await;
         assert!(is_new);
         assert_eq!(
             state.get_rendered("README.md").await,
@@ -112,35 +278,78 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn sync_reports_differences() {
+        let state = AppState::new(PathBuf::from("."), "InspiredGitHub", "Monokai", RenderOptions::default(), FileFilter::default(), MarkdownStyle::default());
+        state.upsert("same.md".into(), doc("same")).await;
+        state.upsert("edited.md".into(), doc("old")).await;
+        state.upsert("gone.md".into(), doc("gone")).await;
+
+        let fresh = BTreeMap::from([
+            ("same.md".to_string(), doc("same")),
+            ("edited.md".to_string(), doc("new")),
+            ("new.md".to_string(), doc("new")),
+        ]);
+        assert_eq!(
+            state.sync("", fresh).await,
+            vec![
+                SseEvent::FileRemoved("gone.md".into()),
+                SseEvent::FileChanged("edited.md".into()),
+                SseEvent::FileAdded("new.md".into()),
+            ]
+        );
+        assert_eq!(state.file_list().await, vec!["edited.md", "new.md", "same.md"]);
+    }
+
+    #[tokio::test]
+    async fn sync_only_touches_prefix() {
+        let state = AppState::new(PathBuf::from("."), "InspiredGitHub", "Monokai", RenderOptions::default(), FileFilter::default(), MarkdownStyle::default());
+        for path in ["docs.md", "docs/a.md", "docs/sub/b.md", "docsx/c.md"] {
+            state.upsert(path.into(), doc(path)).await;
+        }
+        let changes = state.sync("docs/", BTreeMap::from([("docs/a.md".to_string(), doc("docs/a.md"))])).await;
+        assert_eq!(changes, vec![SseEvent::FileRemoved("docs/sub/b.md".into())]);
+        assert_eq!(state.file_list().await, vec!["docs.md", "docs/a.md", "docsx/c.md"]);
+    }
+
+    #[tokio::test]
+    async fn finds_files_under_prefix() {
+        let state = AppState::new(PathBuf::from("."), "InspiredGitHub", "Monokai", RenderOptions::default(), FileFilter::default(), MarkdownStyle::default());
+        state.upsert("docs/a.md".into(), doc("a")).await;
+        assert!(state.has_files_under("docs/").await);
+        assert!(!state.has_files_under("doc/").await);
+        assert!(!state.has_files_under("docs/a.md/").await);
+    }
+
     #[tokio::test]
     async fn upsert_existing_returns_false() {
-        let state = AppState::new(PathBuf::from("."), "InspiredGitHub", "Monokai");
-        state.upsert("a.md".into(), "old".into()).await;
-        let is_new = state.upsert("a.md".into(), "new".into()).await;
+        let state = AppState::new(PathBuf::from("."), "InspiredGitHub", "Monokai", RenderOptions::default(), FileFilter::default(), MarkdownStyle::default());
+        state.upsert("a.md".into(), doc("old")).await;
+        let is_new = state.upsert("a.md".into(), doc("new")).await;
         assert!(!is_new);
         assert_eq!(state.get_rendered("a.md").await, Some("new".into()));
     }
 
     #[tokio::test]
     async fn remove_existing() {
-        let state = AppState::new(PathBuf::from("."), "InspiredGitHub", "Monokai");
-        state.upsert("a.md".into(), "html".into()).await;
+        let state = AppState::new(PathBuf::from("."), "InspiredGitHub", "Monokai", RenderOptions::default(), FileFilter::default(), MarkdownStyle::default());
+        state.upsert("a.md".into(), doc("html")).await;
         assert!(state.remove("a.md").await);
         assert!(state.get_rendered("a.md").await.is_none());
     }
 
     #[tokio::test]
     async fn remove_nonexistent() {
-        let state = AppState::new(PathBuf::from("."), "InspiredGitHub", "Monokai");
+        let state = AppState::new(PathBuf::from("."), "InspiredGitHub", "Monokai", RenderOptions::default(), FileFilter::default(), MarkdownStyle::default());
         assert!(!state.remove("nope.md").await);
     }
 
     #[tokio::test]
     async fn file_list_is_sorted() {
-        let state = AppState::new(PathBuf::from("."), "InspiredGitHub", "Monokai");
-        state.upsert("z.md".into(), "".into()).await;
-        state.upsert("a.md".into(), "".into()).await;
-        state.upsert("m.md".into(), "".into()).await;
+        let state = AppState::new(PathBuf::from("."), "InspiredGitHub", "Monokai", RenderOptions::default(), FileFilter::default(), MarkdownStyle::default());
+        state.upsert("z.md".into(), doc("")).await;
+        state.upsert("a.md".into(), doc("")).await;
+        state.upsert("m.md".into(), doc("")).await;
         assert_eq!(state.file_list().await, vec!["a.md", "m.md", "z.md"]);
     }
 
@@ -151,4 +360,53 @@ mod tests {
         assert!(json.contains(r#""type":"FileChanged""#));
         assert!(json.contains(r#""path":"test.md""#));
     }
+
+    #[tokio::test]
+    async fn get_toc_returns_outline() {
+        let state = AppState::new(PathBuf::from("."), "InspiredGitHub", "Monokai", RenderOptions::default(), FileFilter::default(), MarkdownStyle::default());
+        let toc = vec![TocEntry { level: 1, text: "Intro".into(), slug: "intro".into() }];
+        state.upsert("a.md".into(), Rendered { toc: toc.clone(), ..Default::default() }).await;
+        assert_eq!(state.get_toc("a.md").await, Some(toc));
+        assert!(state.get_toc("nope.md").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn search_tracks_upsert_and_remove() {
+        let state = AppState::new(PathBuf::from("."), "InspiredGitHub", "Monokai", RenderOptions::default(), FileFilter::default(), MarkdownStyle::default());
+        let options = RenderOptions::default();
+        state.load(BTreeMap::from([("a.md".to_string(), render_markdown("apples", "a.md", &options))])).await;
+        assert_eq!(state.search("apples").await.len(), 1);
+
+        state.upsert("b.md".into(), render_markdown("pears", "b.md", &options)).await;
+        assert_eq!(state.search("pears").await.len(), 1);
+
+        state.remove("a.md").await;
+        assert!(state.search("apples").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn get_page_prefers_front_matter_title() {
+        let state = AppState::new(PathBuf::from("."), "InspiredGitHub", "Monokai", RenderOptions::default(), FileFilter::default(), MarkdownStyle::default());
+        let options = RenderOptions::default();
+        state.upsert("a.md".into(), render_markdown("---\ntitle: Alpha\n---\nbody", "a.md", &options)).await;
+        state.upsert("b.md".into(), render_markdown("body", "b.md", &options)).await;
+        assert_eq!(state.get_page("a.md").await.unwrap().0, "Alpha");
+        assert_eq!(state.get_page("b.md").await.unwrap().0, "b.md");
+    }
+
+    #[tokio::test]
+    async fn mounted_roots_tag_search_hits_and_events() {
+        let mounted = RenderOptions { base: "/docs".into(), ..Default::default() };
+        let docs = AppState::new(PathBuf::from("."), "InspiredGitHub", "Monokai", mounted.clone(), FileFilter::default(), MarkdownStyle::default());
+        docs.upsert("a.md".into(), render_markdown("apples", "a.md", &mounted)).await;
+        assert_eq!(docs.name(), "docs");
+        assert_eq!(docs.search("apples").await[0].root, "docs");
+        assert!(Roots::new(vec![Arc::clone(&docs)], vec![None]).is_mounted());
+
+        let single = AppState::new(PathBuf::from("."), "InspiredGitHub", "Monokai", RenderOptions::default(), FileFilter::default(), MarkdownStyle::default());
+        assert!(!Roots::new(vec![single], vec![None]).is_mounted());
+
+        let event = RootEvent { root: docs.name().into(), event: SseEvent::FileChanged("a.md".into()) };
+        assert_eq!(serde_json::to_string(&event).unwrap(), r#"{"root":"docs","type":"FileChanged","path":"a.md"}"#);
+    }
 }
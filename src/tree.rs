@@ -0,0 +1,136 @@
+use serde::Serialize;
+
+/// A node in the nested file tree served by `/api/files?tree=true`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum TreeNode {
+    Dir {
+        name: String,
+        path: String,
+        /// Number of files anywhere beneath this directory.
+        count: usize,
+        children: Vec<TreeNode>,
+    },
+    File {
+        name: String,
+        path: String,
+    },
+}
+
+/// A served root and its files: the top level of the tree when several roots
+/// are served.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename = "root")]
+pub struct RootNode {
+    pub name: String,
+    pub count: usize,
+    pub children: Vec<TreeNode>,
+}
+
+impl RootNode {
+    pub fn new(name: &str, paths: &[String]) -> Self {
+        Self { name: name.to_string(), count: paths.len(), children: build_tree(paths) }
+    }
+}
+
+/// Build a nested tree from relative file paths. Within each directory,
+/// subdirectories come first, then files, each sorted by name.
+pub fn build_tree(paths: &[String]) -> Vec<TreeNode> {
+    let mut root = Vec::new();
+    for path in paths {
+        insert(&mut root, "", path);
+    }
+    sort(&mut root);
+    root
+}
+
+fn insert(nodes: &mut Vec<TreeNode>, prefix: &str, rest: &str) {
+    let full = |name: &str| if prefix.is_empty() { name.to_string() } else { format!("{prefix}/{name}") };
+
+    match rest.split_once('/') {
+        None => nodes.push(TreeNode::File { name: rest.to_string(), path: full(rest) }),
+        Some((dir, remainder)) => {
+            let existing = nodes.iter().position(|n| matches!(n, TreeNode::Dir { name, .. } if name == dir));
+            let index = existing.unwrap_or_else(|| {
+                nodes.push(TreeNode::Dir { name: dir.to_string(), path: full(dir), count: 0, children: Vec::new() });
+                nodes.len() - 1
+            });
+            if let TreeNode::Dir { path, count, children, .. } = &mut nodes[index] {
+                *count += 1;
+                let path = path.clone();
+                insert(children, &path, remainder);
+            }
+        }
+    }
+}
+
+fn sort(nodes: &mut [TreeNode]) {
+    nodes.sort_by(|a, b| match (a, b) {
+        (TreeNode::Dir { name: x, .. }, TreeNode::Dir { name: y, .. })
+        | (TreeNode::File { name: x, .. }, TreeNode::File { name: y, .. }) => x.cmp(y),
+        (TreeNode::Dir { .. }, TreeNode::File { .. }) => std::cmp::Ordering::Less,
+        (TreeNode::File { .. }, TreeNode::Dir { .. }) => std::cmp::Ordering::Greater,
+    });
+    for node in nodes {
+        if let TreeNode::Dir { children, .. } = node {
+            sort(children);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn flat_files_stay_flat() {
+        let tree = build_tree(&paths(&["README.md", "notes.md"]));
+        assert_eq!(
+            tree,
+            vec![
+                TreeNode::File { name: "README.md".into(), path: "README.md".into() },
+                TreeNode::File { name: "notes.md".into(), path: "notes.md".into() },
+            ]
+        );
+    }
+
+    #[test]
+    fn nests_directories_with_counts() {
+        let tree = build_tree(&paths(&["docs/guide/a.md", "docs/guide/b.md", "docs/index.md", "z.md"]));
+        let TreeNode::Dir { name, path, count, children } = &tree[0] else { panic!("expected dir") };
+        assert_eq!((name.as_str(), path.as_str(), *count), ("docs", "docs", 3));
+        let TreeNode::Dir { path, count, children: guide, .. } = &children[0] else { panic!("expected dir") };
+        assert_eq!((path.as_str(), *count), ("docs/guide", 2));
+        assert_eq!(guide[1], TreeNode::File { name: "b.md".into(), path: "docs/guide/b.md".into() });
+        assert_eq!(children[1], TreeNode::File { name: "index.md".into(), path: "docs/index.md".into() });
+        assert_eq!(tree[1], TreeNode::File { name: "z.md".into(), path: "z.md".into() });
+    }
+
+    #[test]
+    fn directories_sort_before_files() {
+        let tree = build_tree(&paths(&["a.md", "b/c.md"]));
+        assert!(matches!(tree[0], TreeNode::Dir { .. }));
+        assert!(matches!(tree[1], TreeNode::File { .. }));
+    }
+
+    #[test]
+    fn serializes_with_type_tag() {
+        let json = serde_json::to_string(&build_tree(&paths(&["d/x.md"]))).unwrap();
+        assert!(json.contains(r#""type":"dir""#));
+        assert!(json.contains(r#""count":1"#));
+        assert!(json.contains(r#""type":"file""#));
+    }
+
+    #[test]
+    fn root_node_wraps_tree() {
+        let root = RootNode::new("docs", &paths(&["a.md", "d/x.md"]));
+        assert_eq!(root.count, 2);
+        assert_eq!(root.children.len(), 2);
+        let json = serde_json::to_string(&root).unwrap();
+        assert!(json.starts_with(r#"{"type":"root","name":"docs","count":2"#), "{json}");
+    }
+}
@@ -1,163 +1,676 @@
-use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher, event::{ModifyKind, RenameMode}};
-use std::path::Path;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use percent_encoding::percent_decode_str;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 use tokio::sync::mpsc;
-use tracing::{info, error};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::time::Instant;
+use tracing::{info, error, warn};
 
+use crate::discovery::{discover_and_render, discover_under};
+use crate::filter::FileFilter;
+use crate::gitignore::is_ignore_file;
 use crate::render::render_markdown;
 use crate::state::{AppState, SseEvent};
 
-fn is_markdown(path: &Path) -> bool {
-    path.extension().and_then(|e| e.to_str()) == Some("md")
-}
-
-fn should_skip(path: &Path) -> bool {
-    path.components().any(|c| {
-        let s = c.as_os_str().to_string_lossy();
-        s.starts_with('.') || s == "node_modules"
-    })
-}
+/// Longest a pending rescan waits for events to stop, in debounce windows.
+const RESCAN_MAX_WINDOWS: u32 = 10;
 
-fn relative_path(path: &Path, root: &Path) -> Option<String> {
-    path.strip_prefix(root)
-        .ok()
-        .map(|p| p.to_string_lossy().to_string())
+/// The root-relative path of `path`, if it is a markdown file accepted by `filter`.
+fn tracked_path(path: &Path, root: &Path, filter: &FileFilter) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?;
+    filter.accepts(relative).then(|| relative.to_string_lossy().to_string())
 }
 
+/// Watch the state's root and keep its files up to date. Events for a path
+/// are coalesced until none has arrived for `debounce`, so an editor saving
+/// with several writes or a write-then-rename causes one render and one SSE
+/// message. If events are lost because the queue overflowed, the whole root
+/// is rescanned instead.
+///
+/// In single-file mode only the root itself is watched, not its
+/// subdirectories, plus the directories of the files the document references.
 pub fn start_watcher(
     state: Arc<AppState>,
+    debounce: Duration,
 ) -> notify::Result<RecommendedWatcher> {
     let root = state.root.clone();
-    let (tx, mut rx) = mpsc::channel::<notify::Result<Event>>(256);
-
-    let mut watcher = RecommendedWatcher::new(
-        move |res| {
-            let _ = tx.try_send(res);
-        },
-        notify::Config::default(),
-    )?;
-
-    watcher.watch(&root, RecursiveMode::Recursive)?;
-    info!(path = %root.display(), "Watching for file changes");
-
-    tokio::spawn(async move {
-        while let Some(result) = rx.recv().await {
-            let event = match result {
-                Ok(e) => e,
-                Err(e) => {
-                    error!(error = %e, "File watcher error");
-                    continue;
-                }
+    let (tx, rx) = mpsc::channel::<notify::Result<Event>>(256);
+    let overflowed = Arc::new(AtomicBool::new(false));
+
+    let mut watcher =
+        RecommendedWatcher::new(forward(tx.clone(), Arc::clone(&overflowed)), notify::Config::default())?;
+
+    let asset_dirs = match state.filter.single_file() {
+        Some(file) => {
+            watcher.watch(&root, RecursiveMode::NonRecursive)?;
+            info!(path = %root.join(file).display(), "Watching for file changes");
+            let asset_watcher = RecommendedWatcher::new(forward(tx, Arc::clone(&overflowed)), notify::Config::default())?;
+            Some(AssetDirs { watcher: asset_watcher, watched: HashSet::new() })
+        }
+        None => {
+            watcher.watch(&root, RecursiveMode::Recursive)?;
+            info!(path = %root.display(), "Watching for file changes");
+            None
+        }
+    };
+
+    tokio::spawn(process_events(state, rx, overflowed, debounce, asset_dirs));
+
+    Ok(watcher)
+}
+
+/// Watches the directories holding a single file's images and other linked
+/// files, which the non-recursive watch on the root does not cover.
+struct AssetDirs {
+    watcher: RecommendedWatcher,
+    watched: HashSet<PathBuf>,
+}
+
+impl AssetDirs {
+    /// Start watching the directories of the files the document currently references.
+    async fn update(&mut self, state: &AppState, file: &str) {
+        for asset in asset_paths(state, file).await {
+            let Some(dir) = state.root.join(&asset).parent().map(Path::to_path_buf) else {
+                continue;
             };
+            if dir == state.root || self.watched.contains(&dir) {
+                continue;
+            }
+            match self.watcher.watch(&dir, RecursiveMode::NonRecursive) {
+                Ok(()) => {
+                    info!(path = %dir.display(), "Watching referenced files");
+                    self.watched.insert(dir);
+                }
+                // Retried after the next change, as the directory may not exist yet
+                Err(e) => warn!(path = %dir.display(), error = %e, "Failed to watch referenced files"),
+            }
+        }
+    }
+}
+
+/// Root-relative paths of the non-markdown files `file` references, such as its images.
+pub async fn asset_paths(state: &AppState, file: &str) -> Vec<String> {
+    let files = state.files.read().await;
+    let Some(doc) = files.get(file) else {
+        return Vec::new();
+    };
+    doc.assets.iter().map(|asset| percent_decode_str(asset).decode_utf8_lossy().into_owned()).collect()
+}
+
+/// The notify callback: queue events without blocking notify's thread, and
+/// raise `overflowed` when the queue is full and an event had to be dropped.
+fn forward(
+    tx: mpsc::Sender<notify::Result<Event>>,
+    overflowed: Arc<AtomicBool>,
+) -> impl Fn(notify::Result<Event>) + Send + 'static {
+    move |res| {
+        if let Err(TrySendError::Full(_)) = tx.try_send(res) {
+            overflowed.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Collect the paths touched by incoming events and settle each one once it
+/// has been quiet for `debounce`. After an overflow, or when notify itself
+/// asks for one, a full rescan replaces the per-path work once events have
+/// been quiet for `debounce`, or at most [`RESCAN_MAX_WINDOWS`] debounce
+/// windows after it was first needed, so a steady stream cannot put it off. Pending work is flushed when the channel closes.
+/// `asset_dirs` follows the single file's references as they change.
+async fn process_events(
+    state: Arc<AppState>,
+    mut rx: mpsc::Receiver<notify::Result<Event>>,
+    overflowed: Arc<AtomicBool>,
+    debounce: Duration,
+    mut asset_dirs: Option<AssetDirs>,
+) {
+    // path -> when it may be settled; pushed back by every new event for the path
+    let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+    let mut rescan_due: Option<Instant> = None;
+    let mut rescan_deadline = Instant::now();
+    let single_file = state.filter.single_file().map(|file| file.to_string_lossy().into_owned());
+    if let (Some(dirs), Some(file)) = (&mut asset_dirs, &single_file) {
+        dirs.update(&state, file).await;
+    }
 
-            match event.kind {
-                EventKind::Modify(ModifyKind::Name(mode)) => {
-                    match mode {
-                        RenameMode::Both => {
-                            if let (Some(from), Some(to)) = (event.paths.first(), event.paths.get(1)) {
-                                if is_markdown(from) && !should_skip(from)
-                                    && let Some(rel) = relative_path(from, &root)
-                                    && state.remove(&rel).await
-                                {
-                                    info!(path = %rel, "File renamed away");
-                                    let _ = state.tx.send(SseEvent::FileRemoved(rel));
-                                }
-                                if is_markdown(to) && !should_skip(to)
-                                    && let Some(rel) = relative_path(to, &root)
-                                    && let Ok(content) = tokio::fs::read_to_string(to).await
-                                {
-                                    let html = render_markdown(&content);
-                                    state.upsert(rel.clone(), html).await;
-                                    info!(path = %rel, "File renamed to");
-                                    let _ = state.tx.send(SseEvent::FileAdded(rel));
-                                }
-                            }
-                        }
-                        RenameMode::From => {
-                            for path in &event.paths {
-                                if !is_markdown(path) || should_skip(path) { continue; }
-                                if let Some(rel) = relative_path(path, &root)
-                                    && state.remove(&rel).await
-                                {
-                                    info!(path = %rel, "File renamed away");
-                                    let _ = state.tx.send(SseEvent::FileRemoved(rel));
-                                }
-                            }
-                        }
-                        RenameMode::To => {
-                            for path in &event.paths {
-                                if !is_markdown(path) || should_skip(path) { continue; }
-                                if let Some(rel) = relative_path(path, &root)
-                                    && let Ok(content) = tokio::fs::read_to_string(path).await
-                                {
-                                    let html = render_markdown(&content);
-                                    state.upsert(rel.clone(), html).await;
-                                    info!(path = %rel, "File renamed to");
-                                    let _ = state.tx.send(SseEvent::FileAdded(rel));
-                                }
-                            }
-                        }
-                        _ => {
-                            // RenameMode::Any (macOS fsevent) -- check if file exists
-                            for path in &event.paths {
-                                if !is_markdown(path) || should_skip(path) { continue; }
-                                if let Some(rel) = relative_path(path, &root) {
-                                    if path.exists() {
-                                        if let Ok(content) = tokio::fs::read_to_string(path).await {
-                                            let html = render_markdown(&content);
-                                            let is_new = state.upsert(rel.clone(), html).await;
-                                            if is_new {
-                                                info!(path = %rel, "File appeared (rename)");
-                                                let _ = state.tx.send(SseEvent::FileAdded(rel));
-                                            } else {
-                                                info!(path = %rel, "File changed (rename)");
-                                                let _ = state.tx.send(SseEvent::FileChanged(rel));
-                                            }
-                                        }
-                                    } else if state.remove(&rel).await {
-                                        info!(path = %rel, "File gone (rename)");
-                                        let _ = state.tx.send(SseEvent::FileRemoved(rel));
-                                    }
-                                }
-                            }
-                        }
+    loop {
+        let next_due = rescan_due.or_else(|| pending.values().min().copied());
+        tokio::select! {
+            received = rx.recv() => match received {
+                Some(Ok(event)) => {
+                    let dropped = overflowed.swap(false, Ordering::Relaxed) || event.need_rescan();
+                    // Changed rules can hide or reveal any file, so rediscover them all
+                    let rules_changed = !matches!(event.kind, EventKind::Access(_) | EventKind::Other)
+                        && event.paths.iter().any(|p| is_ignore_file(p));
+                    if dropped {
+                        warn!("File events were dropped, rescanning");
+                    } else if rules_changed {
+                        info!("Ignore file changed, rescanning");
                     }
-                }
-                EventKind::Create(_) | EventKind::Modify(_) => {
-                    for path in &event.paths {
-                        if !is_markdown(path) || should_skip(path) { continue; }
-                        let rel = match relative_path(path, &root) {
-                            Some(r) => r,
-                            None => continue,
-                        };
-                        if let Ok(content) = tokio::fs::read_to_string(path).await {
-                            let html = render_markdown(&content);
-                            let is_new = state.upsert(rel.clone(), html).await;
-                            let event_kind = if is_new { "added" } else { "changed" };
-                            info!(path = %rel, "File {}", event_kind);
-                            let _ = state.tx.send(if is_new {
-                                SseEvent::FileAdded(rel)
-                            } else {
-                                SseEvent::FileChanged(rel)
-                            });
-                        }
+                    if (dropped || rules_changed) && rescan_due.is_none() {
+                        rescan_deadline = Instant::now() + debounce * RESCAN_MAX_WINDOWS;
+                        rescan_due = Some(Instant::now());
+                    }
+                    if let Some(due) = &mut rescan_due {
+                        *due = (Instant::now() + debounce).min(rescan_deadline);
+                        continue;
+                    }
+                    if matches!(event.kind, EventKind::Access(_) | EventKind::Other) {
+                        continue;
+                    }
+                    let due = Instant::now() + debounce;
+                    for path in event.paths {
+                        pending.insert(path, due);
                     }
                 }
-                EventKind::Remove(_) => {
-                    for path in &event.paths {
-                        if !is_markdown(path) || should_skip(path) { continue; }
-                        if let Some(rel) = relative_path(path, &root)
-                            && state.remove(&rel).await
-                        {
-                            info!(path = %rel, "File removed");
-                            let _ = state.tx.send(SseEvent::FileRemoved(rel));
-                        }
+                Some(Err(e)) => error!(error = %e, "File watcher error"),
+                None => {
+                    if rescan_due.is_some() {
+                        rescan(&state).await;
+                    } else {
+                        let all = pending.keys().cloned().collect();
+                        settle_ready(&state, &mut pending, all).await;
                     }
+                    break;
+                }
+            },
+            _ = tokio::time::sleep_until(next_due.unwrap_or_else(Instant::now)), if next_due.is_some() => {
+                if rescan_due.take().is_some() {
+                    pending.clear();
+                    rescan(&state).await;
+                    continue;
+                }
+                let now = Instant::now();
+                let ready: Vec<PathBuf> = pending.iter().filter(|(_, due)| **due <= now).map(|(p, _)| p.clone()).collect();
+                settle_ready(&state, &mut pending, ready).await;
+                if let (Some(dirs), Some(file)) = (&mut asset_dirs, &single_file) {
+                    dirs.update(&state, file).await;
                 }
-                _ => {}
             }
         }
-    });
+    }
+}
 
-    Ok(watcher)
+/// Settle the `ready` paths and remove them from `pending`. A directory
+/// settles everything under it, so paths inside a directory that is still
+/// pending wait for it, and paths inside one settled here are dropped: each
+/// file is rendered and announced once.
+async fn settle_ready(state: &AppState, pending: &mut HashMap<PathBuf, Instant>, mut ready: Vec<PathBuf>) {
+    ready.sort_by_key(|path| path.components().count());
+    for path in ready {
+        if pending.remove(&path).is_none() || pending.keys().any(|other| path.starts_with(other)) {
+            continue;
+        }
+        settle(state, &path).await;
+        pending.retain(|other, _| !other.starts_with(&path));
+    }
+}
+
+/// Rediscover every file under the root and replace the state with the
+/// result, sending an SSE message for each file that was added, removed or
+/// changed. Returns those messages.
+pub async fn rescan(state: &Arc<AppState>) -> Vec<SseEvent> {
+    state.filter.reload_ignore_files();
+    let (root, options, filter) = (state.root.clone(), state.render_options.clone(), state.filter.clone());
+    let docs = match tokio::task::spawn_blocking(move || discover_and_render(&root, &options, &filter)).await {
+        Ok(docs) => docs,
+        Err(e) => {
+            error!(error = %e, "Rescan failed");
+            return Vec::new();
+        }
+    };
+    let count = docs.len();
+    let changes = state.sync("", docs).await;
+    info!(count, changed = changes.len(), "Rescanned markdown files");
+    for change in &changes {
+        let _ = state.tx.send(change.clone());
+    }
+    changes
+}
+
+/// Bring one path in the state in line with the disk: render it if it
+/// exists, drop it if it is gone, and tell clients what happened.
+async fn settle(state: &AppState, path: &Path) {
+    let Some(rel) = tracked_path(path, &state.root, &state.filter) else {
+        if let Some(file) = state.filter.single_file() {
+            settle_asset(state, path, &file.to_string_lossy()).await;
+        } else if let Ok(relative) = path.strip_prefix(&state.root)
+            && !relative.as_os_str().is_empty()
+            && (path.is_dir() || state.has_files_under(&dir_prefix(relative)).await)
+        {
+            settle_dir(state, path, relative).await;
+        }
+        return;
+    };
+    match tokio::fs::read_to_string(path).await {
+        Ok(content) => {
+            let rendered = render_markdown(&content, &rel, &state.render_options);
+            let is_new = state.upsert(rel.clone(), rendered).await;
+            let event_kind = if is_new { "added" } else { "changed" };
+            info!(path = %rel, "File {}", event_kind);
+            let _ = state.tx.send(if is_new {
+                SseEvent::FileAdded(rel)
+            } else {
+                SseEvent::FileChanged(rel)
+            });
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            if state.remove(&rel).await {
+                info!(path = %rel, "File removed");
+                let _ = state.tx.send(SseEvent::FileRemoved(rel));
+            }
+        }
+        Err(e) => error!(path = %rel, error = %e, "Failed to read changed file"),
+    }
+}
+
+/// In single-file mode, reload the document when a file it references, such
+/// as an image, changed.
+async fn settle_asset(state: &AppState, path: &Path, file: &str) {
+    let Ok(relative) = path.strip_prefix(&state.root) else {
+        return;
+    };
+    let relative = relative.to_string_lossy();
+    if asset_paths(state, file).await.iter().any(|asset| *asset == relative) {
+        info!(path = %relative, "Referenced file changed");
+        let _ = state.tx.send(SseEvent::FileChanged(file.to_string()));
+    }
+}
+
+/// The state key prefix of the files under the root-relative directory `relative`.
+fn dir_prefix(relative: &Path) -> String {
+    format!("{}/", relative.to_string_lossy())
+}
+
+/// A directory that was created, moved or deleted (it exists as one, or
+/// files are tracked under it): rediscover everything under it, or drop it all if the
+/// directory is gone, with one SSE message per affected file.
+async fn settle_dir(state: &AppState, path: &Path, relative: &Path) {
+    let docs = if path.is_dir() && !state.filter.skip_dir(relative) {
+        let (root, dir, options, filter) =
+            (state.root.clone(), path.to_path_buf(), state.render_options.clone(), state.filter.clone());
+        tokio::task::spawn_blocking(move || discover_under(&root, &dir, &options, &filter))
+            .await
+            .unwrap_or_default()
+    } else {
+        Default::default()
+    };
+    for change in state.sync(&dir_prefix(relative), docs).await {
+        info!(change = ?change, "Directory changed");
+        let _ = state.tx.send(change);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::MarkdownStyle;
+    use crate::render::RenderOptions;
+    use notify::event::{CreateKind, DataChange, ModifyKind, RemoveKind, RenameMode};
+    use tokio::sync::broadcast;
+
+    const WINDOW: Duration = Duration::from_millis(50);
+
+    fn setup_state(name: &str) -> Arc<AppState> {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(format!("_scratch/watcher_{name}"));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        let root = root.canonicalize().unwrap();
+        AppState::new(
+            root,
+            "InspiredGitHub",
+            "Monokai",
+            RenderOptions::default(),
+            FileFilter::default(),
+            MarkdownStyle::default(),
+        )
+    }
+
+    /// Feed synthetic events to the watcher loop, as notify would.
+    fn start(state: &Arc<AppState>, window: Duration) -> (mpsc::Sender<notify::Result<Event>>, tokio::task::JoinHandle<()>) {
+        start_with_flag(state, window, Arc::new(AtomicBool::new(false)))
+    }
+
+    fn start_with_flag(
+        state: &Arc<AppState>,
+        window: Duration,
+        overflowed: Arc<AtomicBool>,
+    ) -> (mpsc::Sender<notify::Result<Event>>, tokio::task::JoinHandle<()>) {
+        let (tx, rx) = mpsc::channel(256);
+        (tx, tokio::spawn(process_events(Arc::clone(state), rx, overflowed, window, None)))
+    }
+
+    fn event(kind: EventKind, paths: &[&Path]) -> notify::Result<Event> {
+        Ok(paths.iter().fold(Event::new(kind), |e, p| e.add_path(p.to_path_buf())))
+    }
+
+    async fn next(rx: &mut broadcast::Receiver<SseEvent>) -> SseEvent {
+        tokio::time::timeout(Duration::from_secs(2), rx.recv()).await.expect("no SSE event").unwrap()
+    }
+
+    async fn assert_quiet(rx: &mut broadcast::Receiver<SseEvent>) {
+        tokio::time::sleep(WINDOW * 3).await;
+        assert!(rx.try_recv().is_err(), "unexpected extra event");
+    }
+
+    #[tokio::test]
+    async fn several_writes_are_one_change() {
+        let state = setup_state("writes");
+        let path = state.root.join("a.md");
+        std::fs::write(&path, "# Old").unwrap();
+        state.upsert("a.md".into(), render_markdown("# Old", "a.md", &state.render_options)).await;
+        let mut sse = state.tx.subscribe();
+        let (tx, _task) = start(&state, WINDOW);
+
+        std::fs::write(&path, "# New").unwrap();
+        for _ in 0..3 {
+            tx.send(event(EventKind::Modify(ModifyKind::Data(DataChange::Content)), &[&path])).await.unwrap();
+        }
+        tx.send(event(EventKind::Modify(ModifyKind::Any), &[&path])).await.unwrap();
+
+        assert_eq!(next(&mut sse).await, SseEvent::FileChanged("a.md".into()));
+        assert_quiet(&mut sse).await;
+        assert!(state.get_rendered("a.md").await.unwrap().contains("New"));
+    }
+
+    #[tokio::test]
+    async fn write_then_rename_is_one_add() {
+        let state = setup_state("rename");
+        let tmp = state.root.join("a.md.tmp");
+        let path = state.root.join("a.md");
+        let mut sse = state.tx.subscribe();
+        let (tx, _task) = start(&state, WINDOW);
+
+        std::fs::write(&tmp, "# Saved").unwrap();
+        tx.send(event(EventKind::Create(CreateKind::File), &[&tmp])).await.unwrap();
+        tx.send(event(EventKind::Modify(ModifyKind::Data(DataChange::Any)), &[&tmp])).await.unwrap();
+        std::fs::rename(&tmp, &path).unwrap();
+        tx.send(event(EventKind::Modify(ModifyKind::Name(RenameMode::From)), &[&tmp])).await.unwrap();
+        tx.send(event(EventKind::Modify(ModifyKind::Name(RenameMode::To)), &[&path])).await.unwrap();
+        tx.send(event(EventKind::Modify(ModifyKind::Name(RenameMode::Both)), &[&tmp, &path])).await.unwrap();
+
+        assert_eq!(next(&mut sse).await, SseEvent::FileAdded("a.md".into()));
+        assert_quiet(&mut sse).await;
+
+        std::fs::remove_file(&path).unwrap();
+        tx.send(event(EventKind::Remove(RemoveKind::File), &[&path])).await.unwrap();
+        assert_eq!(next(&mut sse).await, SseEvent::FileRemoved("a.md".into()));
+    }
+
+    #[tokio::test]
+    async fn ignore_file_change_rescans() {
+        let state = setup_state("gitignore");
+        let root = state.root.clone();
+        let filter = FileFilter::default().with_ignore_files(&root);
+        let state = AppState::new(
+            root.clone(),
+            "InspiredGitHub",
+            "Monokai",
+            RenderOptions::default(),
+            filter,
+            MarkdownStyle::default(),
+        );
+        std::fs::write(root.join(".gitignore"), "a.md\n").unwrap();
+        std::fs::write(root.join("a.md"), "# A").unwrap();
+        std::fs::write(root.join("b.md"), "# B").unwrap();
+        rescan(&state).await;
+        assert_eq!(state.file_list().await, vec!["b.md".to_string()]);
+        let mut sse = state.tx.subscribe();
+        let (tx, _task) = start(&state, WINDOW);
+
+        let gitignore = root.join(".gitignore");
+        std::fs::write(&gitignore, "b.md\n").unwrap();
+        tx.send(event(EventKind::Modify(ModifyKind::Data(DataChange::Content)), &[&gitignore])).await.unwrap();
+
+        let mut changes = vec![next(&mut sse).await, next(&mut sse).await];
+        changes.sort_by_key(|change| format!("{change:?}"));
+        assert_eq!(changes, vec![SseEvent::FileAdded("a.md".into()), SseEvent::FileRemoved("b.md".into())]);
+        assert_quiet(&mut sse).await;
+        assert_eq!(state.file_list().await, vec!["a.md".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn separate_saves_are_separate_changes() {
+        let state = setup_state("separate");
+        let path = state.root.join("a.md");
+        std::fs::write(&path, "one").unwrap();
+        let mut sse = state.tx.subscribe();
+        let (tx, _task) = start(&state, WINDOW);
+
+        tx.send(event(EventKind::Create(CreateKind::File), &[&path])).await.unwrap();
+        assert_eq!(next(&mut sse).await, SseEvent::FileAdded("a.md".into()));
+        std::fs::write(&path, "two").unwrap();
+        tx.send(event(EventKind::Modify(ModifyKind::Any), &[&path])).await.unwrap();
+        assert_eq!(next(&mut sse).await, SseEvent::FileChanged("a.md".into()));
+    }
+
+    #[tokio::test]
+    async fn single_file_reloads_for_its_images_only() {
+        let dir = setup_state("single").root.clone();
+        std::fs::create_dir_all(dir.join("img")).unwrap();
+        std::fs::write(dir.join("notes.md"), "![pic](img/a%20b.png)").unwrap();
+        std::fs::write(dir.join("other.md"), "other").unwrap();
+        std::fs::write(dir.join("img/a b.png"), "").unwrap();
+        std::fs::write(dir.join("img/unused.png"), "").unwrap();
+        let filter = FileFilter::default().with_single_file(Path::new("notes.md"));
+        let state = AppState::new(dir.clone(), "InspiredGitHub", "Monokai", RenderOptions::default(), filter, MarkdownStyle::default());
+        state.load(discover_and_render(&dir, &state.render_options, &state.filter)).await;
+        assert_eq!(state.file_list().await, vec!["notes.md"]);
+        let mut sse = state.tx.subscribe();
+        let (tx, _task) = start(&state, WINDOW);
+
+        let modify = EventKind::Modify(ModifyKind::Data(DataChange::Content));
+        tx.send(event(modify, &[&dir.join("other.md"), &dir.join("img/unused.png")])).await.unwrap();
+        assert_quiet(&mut sse).await;
+
+        tx.send(event(modify, &[&dir.join("img/a b.png")])).await.unwrap();
+        assert_eq!(next(&mut sse).await, SseEvent::FileChanged("notes.md".into()));
+        assert_quiet(&mut sse).await;
+    }
+
+    #[tokio::test]
+    async fn copied_tree_announces_each_file_once() {
+        let state = setup_state("dir_copy");
+        let dir = state.root.join("docs");
+        let mut sse = state.tx.subscribe();
+        let (tx, _task) = start(&state, WINDOW);
+
+        // As `cp -r` reports it: the directory, then the files written into it.
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        tx.send(event(EventKind::Create(CreateKind::Folder), &[&dir])).await.unwrap();
+        for file in [dir.join("a.md"), dir.join("sub/b.md")] {
+            std::fs::write(&file, "text").unwrap();
+            tx.send(event(EventKind::Create(CreateKind::File), &[&file])).await.unwrap();
+            tokio::time::sleep(WINDOW / 4).await;
+            tx.send(event(EventKind::Modify(ModifyKind::Data(DataChange::Any)), &[&file])).await.unwrap();
+        }
+
+        let mut received = vec![next(&mut sse).await, next(&mut sse).await];
+        received.sort_by_key(|e| format!("{e:?}"));
+        assert_eq!(received, vec![SseEvent::FileAdded("docs/a.md".into()), SseEvent::FileAdded("docs/sub/b.md".into())]);
+        assert_quiet(&mut sse).await;
+    }
+
+    #[tokio::test]
+    async fn directory_rename_moves_its_files() {
+        let state = setup_state("dir_rename");
+        let (old, new) = (state.root.join("old"), state.root.join("new"));
+        std::fs::create_dir_all(old.join("sub")).unwrap();
+        std::fs::write(old.join("a.md"), "a").unwrap();
+        std::fs::write(old.join("sub/b.md"), "b").unwrap();
+        state.load(discover_and_render(&state.root, &state.render_options, &state.filter)).await;
+        let mut sse = state.tx.subscribe();
+        let (tx, _task) = start(&state, WINDOW);
+
+        std::fs::rename(&old, &new).unwrap();
+        tx.send(event(EventKind::Modify(ModifyKind::Name(RenameMode::Both)), &[&old, &new])).await.unwrap();
+
+        let mut received: Vec<SseEvent> = Vec::new();
+        for _ in 0..4 {
+            received.push(next(&mut sse).await);
+        }
+        received.sort_by_key(|e| format!("{e:?}"));
+        assert_eq!(
+            received,
+            vec![
+                SseEvent::FileAdded("new/a.md".into()),
+                SseEvent::FileAdded("new/sub/b.md".into()),
+                SseEvent::FileRemoved("old/a.md".into()),
+                SseEvent::FileRemoved("old/sub/b.md".into()),
+            ]
+        );
+        assert_quiet(&mut sse).await;
+    }
+
+    #[tokio::test]
+    async fn directory_removal_removes_its_files() {
+        let state = setup_state("dir_remove");
+        let dir = state.root.join("docs");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.md"), "a").unwrap();
+        std::fs::write(state.root.join("docs.md"), "top").unwrap();
+        state.load(discover_and_render(&state.root, &state.render_options, &state.filter)).await;
+        let mut sse = state.tx.subscribe();
+        let (tx, _task) = start(&state, WINDOW);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        tx.send(event(EventKind::Remove(RemoveKind::Folder), &[&dir])).await.unwrap();
+
+        assert_eq!(next(&mut sse).await, SseEvent::FileRemoved("docs/a.md".into()));
+        assert_quiet(&mut sse).await;
+        assert_eq!(state.file_list().await, vec!["docs.md"]);
+    }
+
+    #[tokio::test]
+    async fn created_directory_is_discovered() {
+        let state = setup_state("dir_create");
+        let dir = state.root.join("notes");
+        let mut sse = state.tx.subscribe();
+        let (tx, _task) = start(&state, WINDOW);
+
+        std::fs::create_dir_all(dir.join("node_modules")).unwrap();
+        std::fs::write(dir.join("a.md"), "a").unwrap();
+        std::fs::write(dir.join("node_modules/skip.md"), "skip").unwrap();
+        tx.send(event(EventKind::Create(CreateKind::Folder), &[&dir])).await.unwrap();
+
+        assert_eq!(next(&mut sse).await, SseEvent::FileAdded("notes/a.md".into()));
+        assert_quiet(&mut sse).await;
+    }
+
+    #[test]
+    fn full_queue_raises_overflow() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let overflowed = Arc::new(AtomicBool::new(false));
+        let callback = forward(tx, Arc::clone(&overflowed));
+        callback(event(EventKind::Any, &[Path::new("a.md")]));
+        assert!(!overflowed.load(Ordering::Relaxed));
+        callback(event(EventKind::Any, &[Path::new("b.md")]));
+        assert!(overflowed.load(Ordering::Relaxed));
+        assert!(rx.try_recv().is_ok());
+    }
+
+    #[tokio::test]
+    async fn steady_events_do_not_postpone_rescan() {
+        let state = setup_state("overflow_steady");
+        let busy = state.root.join("busy.txt");
+        std::fs::write(state.root.join("added.md"), "added").unwrap();
+        let mut sse = state.tx.subscribe();
+        let (tx, _task) = start_with_flag(&state, WINDOW, Arc::new(AtomicBool::new(true)));
+
+        // A build writing into the tree without pause, for longer than the cap.
+        let writer = tokio::spawn(async move {
+            for _ in 0..200 {
+                tx.send(event(EventKind::Modify(ModifyKind::Any), &[&busy])).await.unwrap();
+                tokio::time::sleep(WINDOW / 5).await;
+            }
+        });
+        let added = tokio::time::timeout(WINDOW * (RESCAN_MAX_WINDOWS + 4), sse.recv()).await;
+        assert_eq!(added.expect("rescan was postponed").unwrap(), SseEvent::FileAdded("added.md".into()));
+        writer.abort();
+    }
+
+    #[tokio::test]
+    async fn overflow_triggers_rescan() {
+        let state = setup_state("overflow");
+        std::fs::write(state.root.join("edited.md"), "new").unwrap();
+        std::fs::write(state.root.join("same.md"), "same").unwrap();
+        std::fs::write(state.root.join("added.md"), "added").unwrap();
+        for (path, text) in [("edited.md", "old"), ("same.md", "same"), ("gone.md", "gone")] {
+            state.upsert(path.into(), render_markdown(text, path, &state.render_options)).await;
+        }
+        let mut sse = state.tx.subscribe();
+        let overflowed = Arc::new(AtomicBool::new(true));
+        let (tx, _task) = start_with_flag(&state, WINDOW, Arc::clone(&overflowed));
+
+        // Only the event that got through; the rest were dropped.
+        let same = state.root.join("same.md");
+        tx.send(event(EventKind::Modify(ModifyKind::Any), &[&same])).await.unwrap();
+
+        let mut received = vec![next(&mut sse).await, next(&mut sse).await, next(&mut sse).await];
+        received.sort_by_key(|e| format!("{e:?}"));
+        assert_eq!(
+            received,
+            vec![
+                SseEvent::FileAdded("added.md".into()),
+                SseEvent::FileChanged("edited.md".into()),
+                SseEvent::FileRemoved("gone.md".into()),
+            ]
+        );
+        assert_quiet(&mut sse).await;
+        assert!(!overflowed.load(Ordering::Relaxed));
+        assert_eq!(state.file_list().await, vec!["added.md", "edited.md", "same.md"]);
+    }
+
+    #[tokio::test]
+    async fn pending_paths_are_flushed_on_close() {
+        let state = setup_state("flush");
+        let path = state.root.join("a.md");
+        std::fs::write(&path, "x").unwrap();
+        let mut sse = state.tx.subscribe();
+        let (tx, task) = start(&state, Duration::from_secs(60));
+
+        tx.send(event(EventKind::Create(CreateKind::File), &[&path])).await.unwrap();
+        drop(tx);
+        task.await.unwrap();
+        assert_eq!(sse.try_recv().unwrap(), SseEvent::FileAdded("a.md".into()));
+    }
+
+    #[test]
+    fn tracks_markdown_under_root() {
+        let root = Path::new("/srv/notes");
+        let filter = FileFilter::default();
+        assert_eq!(tracked_path(Path::new("/srv/notes/a/b.markdown"), root, &filter), Some("a/b.markdown".into()));
+        assert_eq!(tracked_path(Path::new("/srv/notes/c.mdx"), root, &filter), Some("c.mdx".into()));
+        assert_eq!(tracked_path(Path::new("/srv/notes/img.png"), root, &filter), None);
+        assert_eq!(tracked_path(Path::new("/srv/notes/.git/x.md"), root, &filter), None);
+        assert_eq!(tracked_path(Path::new("/elsewhere/x.md"), root, &filter), None);
+    }
+
+    #[test]
+    fn applies_include_and_exclude_globs() {
+        let root = Path::new("/srv/notes");
+        let filter = FileFilter::new(&["docs/**".to_string()], &["drafts".to_string()]).unwrap();
+        assert_eq!(tracked_path(Path::new("/srv/notes/docs/a.md"), root, &filter), Some("docs/a.md".into()));
+        assert_eq!(tracked_path(Path::new("/srv/notes/docs/drafts/a.md"), root, &filter), None);
+        assert_eq!(tracked_path(Path::new("/srv/notes/README.md"), root, &filter), None);
+    }
+
+    #[test]
+    fn skips_gitignored_paths() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("_scratch/watcher_gitignore");
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join(".gitignore"), "dist/\n").unwrap();
+
+        let filter = FileFilter::default().with_ignore_files(&root);
+        assert_eq!(tracked_path(&root.join("dist/out.md"), &root, &filter), None);
+        assert_eq!(tracked_path(&root.join("src/doc.md"), &root, &filter), Some("src/doc.md".into()));
+
+        std::fs::write(root.join(".gitignore"), "").unwrap();
+        filter.reload_ignore_files();
+        assert_eq!(tracked_path(&root.join("dist/out.md"), &root, &filter), Some("dist/out.md".into()));
+    }
 }